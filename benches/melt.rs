@@ -0,0 +1,69 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use libc::c_char;
+use rakaly::{
+    rakaly_file_meta, rakaly_file_meta_melt_or_null, rakaly_file_or_null, rakaly_free_file,
+    rakaly_free_melt, rakaly_melt_data_length, rakaly_set_tokens,
+};
+use std::path::Path;
+
+/// The saves that CI downloads and the `PdsGame` value of each
+const SAVES: &[(&str, u32)] = &[
+    ("kandy2.bin.eu4", 1),
+    ("af_Munso_867_Ironman.ck3", 2),
+    ("observer1.5.rome", 3),
+    ("1.10-ironman.hoi4", 4),
+];
+
+/// Metadata melts are small enough that their cost is dominated by setup,
+/// like building the token resolver. Melts that share the resolver built by
+/// an earlier melt are compared with melts that rebuild it, as happens after
+/// tokens are loaded.
+fn meta_melt(c: &mut Criterion) {
+    let mut group = c.benchmark_group("meta_melt");
+    for &(name, game) in SAVES {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("assets")
+            .join("saves")
+            .join(name);
+
+        let Ok(data) = std::fs::read(&path) else {
+            eprintln!("skipping, {} is missing", path.display());
+            continue;
+        };
+
+        unsafe {
+            let file = rakaly_file_or_null(data.as_ptr() as *const c_char, data.len());
+            assert!(!file.is_null(), "{}", name);
+
+            let meta = rakaly_file_meta(file);
+            if meta.is_null() {
+                rakaly_free_file(file);
+                continue;
+            }
+
+            let melt = || {
+                let melted = rakaly_file_meta_melt_or_null(meta);
+                assert!(!melted.is_null(), "{}", name);
+                let len = rakaly_melt_data_length(melted);
+                rakaly_free_melt(melted);
+                len
+            };
+
+            group.bench_function(BenchmarkId::new("cached", name), |b| b.iter(melt));
+            group.bench_function(BenchmarkId::new("rebuilt", name), |b| {
+                b.iter(|| {
+                    // Restoring the embedded tokens discards the resolver
+                    let err = rakaly_set_tokens(game, std::ptr::null(), 0, 0);
+                    assert!(err.is_null());
+                    melt()
+                })
+            });
+
+            rakaly_free_file(file);
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, meta_melt);
+criterion_main!(benches);
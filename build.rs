@@ -13,12 +13,39 @@ fn main() {
     let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
     let out_path = Path::new(&crate_dir).join("target").join("rakaly.h");
 
+    // Prefix enum variants with the enum name as C does not namespace them
+    let config = cbindgen::Config {
+        enumeration: cbindgen::EnumConfig {
+            prefix_with_name: true,
+            ..Default::default()
+        },
+        // Bit flags that are returned as integers and enums that are accepted
+        // as integers aren't otherwise referenced
+        export: cbindgen::ExportConfig {
+            include: [
+                "PdsValueKind",
+                "PdsFailedResolve",
+                "PdsJsonDuplicateKeys",
+                "PdsSummaryField",
+                "PdsLogLevel",
+                "PdsTokensMode",
+            ]
+            .iter()
+            .map(|x| String::from(*x))
+            .collect(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
     cbindgen::Builder::new()
+        .with_config(config)
         .with_cpp_compat(true)
         .with_crate(crate_dir)
         .with_language(cbindgen::Language::C)
         .with_no_includes()
         .with_include("stddef.h")
+        .with_include("stdint.h")
         .with_trailer(include_str!("./src/cpp_helper.h"))
         .generate()
         .expect("Unable to generate bindings")
@@ -15,24 +15,6 @@ std::string readFile(fs::path filePath) {
   return result;
 }
 
-rakaly::GameFile parseSave(fs::path filePath, const std::string &input) {
-  if (filePath.extension() == ".eu4") {
-    return rakaly::parseEu4(input);
-  } else if (filePath.extension() == ".ck3") {
-    return rakaly::parseCk3(input);
-  } else if (filePath.extension() == ".hoi4") {
-    return rakaly::parseHoi4(input);
-  } else if (filePath.extension() == ".rome") {
-    return rakaly::parseImperator(input);
-  } else if (filePath.extension() == ".v3") {
-    return rakaly::parseVic3(input);
-  } else if (filePath.extension() == ".eu5") {
-    return rakaly::parseEu5(input);
-  } else {
-    throw std::runtime_error("unrecognized file extension");
-  }
-}
-
 int main(int argc, const char *argv[]) {
   if (argc != 3) {
     std::cerr << "expected [meta/save] and one file argument\n";
@@ -42,7 +24,7 @@ int main(int argc, const char *argv[]) {
   fs::path filePath = argv[2];
   std::string input = readFile(filePath);
 
-  const auto save = parseSave(filePath, input);
+  const auto save = rakaly::parseSave(input);
   if (save.is_binary()) {
     std::cerr << "cool! This save is binary!\n";
   }
@@ -3,19 +3,53 @@
 #define RAKALY_WRAPPER_H
 
 #include <optional>
+#include <ostream>
 #include <stdexcept>
 #include <string>
+#include <string_view>
+#include <vector>
 
 namespace rakaly {
 
+class Error : public std::runtime_error {
+  PdsErrorCode code_;
+  int64_t offset_;
+  std::string key_path_;
+
+public:
+  Error(const std::string &msg, PdsErrorCode code, int64_t offset,
+        const std::string &key_path)
+      : std::runtime_error(msg), code_(code), offset_(offset),
+        key_path_(key_path) {}
+
+  PdsErrorCode code() const { return code_; }
+
+  /**
+   * The byte offset into the parsed data where the error occurred, or -1 when
+   * it is not known
+   */
+  int64_t offset() const { return offset_; }
+
+  /**
+   * The key path that was being melted when the error occurred, or empty when
+   * it is not known
+   */
+  const std::string &keyPath() const { return key_path_; }
+};
+
 void unwrapError(PdsError *err) {
   if (err != nullptr) {
     int error_len = rakaly_error_length(err);
     std::string error(error_len, ' ');
     rakaly_error_write_data(err, error.data(), error_len);
+    int path_len = rakaly_error_key_path_length(err);
+    std::string path(path_len, ' ');
+    rakaly_error_write_key_path(err, path.data(), path_len);
+    PdsErrorCode code = rakaly_error_code(err);
+    int64_t offset = rakaly_error_offset(err);
     rakaly_free_error(err);
     auto msg = std::string("librakaly returned an error ") + error;
-    throw std::runtime_error(msg);
+    throw Error(msg, code, offset, path);
   }
 }
 
@@ -45,13 +79,106 @@ public:
     }
   }
 
+  /**
+   * Returns views of the melted output without copying it. The views are only
+   * valid for the lifetime of this object and are empty when the melter
+   * performed no work on the input.
+   */
+  std::vector<std::string_view> segments() const {
+    size_t count = rakaly_melt_segment_count(melt);
+    std::vector<std::string_view> segments;
+    segments.reserve(count);
+    for (size_t i = 0; i < count; i++) {
+      segments.emplace_back(rakaly_melt_segment_ptr(melt, i),
+                            rakaly_melt_segment_length(melt, i));
+    }
+    return segments;
+  }
+
   bool has_unknown_tokens() const {
     return rakaly_melt_binary_unknown_tokens(melt);
   }
 
+  std::vector<PdsUnknownToken> unknown_tokens() const {
+    size_t count = rakaly_melt_unknown_tokens_count(melt);
+    std::vector<PdsUnknownToken> tokens;
+    tokens.reserve(count);
+    for (size_t i = 0; i < count; i++) {
+      tokens.push_back(rakaly_melt_unknown_token(melt, i));
+    }
+    return tokens;
+  }
+
+  /**
+   * Returns the key path where the unknown token at the given index was first
+   * encountered. Only available when melted with diagnostics enabled.
+   */
+  std::string unknown_token_path(size_t index) const {
+    int len = rakaly_melt_unknown_token_path_length(melt, index);
+    std::string path(len, ' ');
+    if (rakaly_melt_unknown_token_write_path(melt, index, path.data(), len) !=
+        len) {
+      throw std::runtime_error("librakaly failed to copy data.");
+    }
+    return path;
+  }
+
+  /**
+   * Returns the `PdsValueKind` flags of the values that the unknown token at
+   * the given index was used with. Only available when melted with
+   * diagnostics enabled.
+   */
+  uint32_t unknown_token_value_kinds(size_t index) const {
+    return rakaly_melt_unknown_token_value_kinds(melt, index);
+  }
+
   virtual ~MeltedOutput() { rakaly_free_melt(melt); }
 };
 
+class Summary {
+  PdsSummary *summary;
+
+  Summary(const Summary &) = delete;
+
+  std::optional<std::string> field(PdsSummaryField field) const {
+    if (!rakaly_summary_has_field(summary, field)) {
+      return std::nullopt;
+    }
+
+    int len = rakaly_summary_field_length(summary, field);
+    std::string value(len, ' ');
+    if (rakaly_summary_write_field(summary, field, value.data(), len) != len) {
+      throw std::runtime_error("librakaly failed to copy data.");
+    }
+    return value;
+  }
+
+public:
+  Summary(PdsSummary *summary) { this->summary = summary; }
+
+  PdsGame game() const { return rakaly_summary_game(summary); }
+
+  std::optional<std::string> version() const {
+    return field(PdsSummaryField_Version);
+  }
+
+  std::optional<std::string> date() const { return field(PdsSummaryField_Date); }
+
+  std::optional<std::string> player() const {
+    return field(PdsSummaryField_Player);
+  }
+
+  std::optional<std::string> saveName() const {
+    return field(PdsSummaryField_SaveName);
+  }
+
+  bool ironman() const { return rakaly_summary_ironman(summary); }
+
+  bool multiplayer() const { return rakaly_summary_multiplayer(summary); }
+
+  virtual ~Summary() { rakaly_free_summary(summary); }
+};
+
 class GameFile {
   PdsFile *file;
 
@@ -62,6 +189,10 @@ public:
 
   bool is_binary() const { return rakaly_file_is_binary(file); }
 
+  PdsGame game() const { return rakaly_file_game(file); }
+
+  PdsEncoding encoding() const { return rakaly_file_encoding(file); }
+
   std::optional<MeltedOutput> meltMeta() const {
     PdsMeta *meta = rakaly_file_meta(file);
     if (meta == nullptr) {
@@ -79,9 +210,121 @@ public:
     return MeltedOutput(rakaly_melt_value(melt_result));
   }
 
+  Summary summary() const {
+    PdsSummaryResult *summary_result = rakaly_file_summary(file);
+    unwrapError(rakaly_summary_error(summary_result));
+    return Summary(rakaly_summary_value(summary_result));
+  }
+
+  /**
+   * Converts the save to JSON, melting it first if it is binary
+   */
+  std::string
+  toJson(bool pretty = false,
+         PdsJsonDuplicateKeys keys = PdsJsonDuplicateKeys_Preserve) const {
+    MeltedBufferResult *json_result =
+        rakaly_file_to_json(file, nullptr, pretty, keys);
+    unwrapError(rakaly_melt_error(json_result));
+    MeltedOutput output(rakaly_melt_value(json_result));
+    std::string json;
+    output.writeData(json);
+    return json;
+  }
+
+  /**
+   * Streams the melted output into the given stream without buffering the
+   * entire output in memory. Saves that are already plaintext are written to
+   * the stream as well.
+   */
+  MeltedOutput meltTo(std::ostream &out) const {
+    auto write = [](void *user_data, const char *data, size_t len) -> int {
+      auto stream = static_cast<std::ostream *>(user_data);
+      stream->write(data, len);
+      return stream->good() ? 0 : 1;
+    };
+
+    MeltedBufferResult *melt_result =
+        rakaly_file_melt_to_writer(file, nullptr, write, &out);
+    unwrapError(rakaly_melt_error(melt_result));
+    return MeltedOutput(rakaly_melt_value(melt_result));
+  }
+
+  /**
+   * Writes the melted output to the file at the given path
+   */
+  void meltToPath(const std::string &path) const {
+    unwrapError(rakaly_file_melt_to_path(file, nullptr, path.c_str()));
+  }
+
   virtual ~GameFile() { rakaly_free_file(file); }
 };
 
+/**
+ * Supplements or replaces the binary tokens of a game with the given token
+ * lines for all subsequent melts. Empty data restores the embedded tokens.
+ */
+void setTokens(PdsGame game, const std::string &data,
+               PdsTokensMode mode = PdsTokensMode_Extend) {
+  unwrapError(rakaly_set_tokens(game, data.c_str(), data.length(), mode));
+}
+
+/**
+ * Supplements or replaces the binary tokens of a game with the tokens in the
+ * file at the given path for all subsequent melts.
+ */
+void loadTokens(PdsGame game, const std::string &path,
+                PdsTokensMode mode = PdsTokensMode_Extend) {
+  unwrapError(rakaly_load_tokens_path(game, path.c_str(), mode));
+}
+
+/**
+ * Returns the binary token id of the field with the given name
+ */
+std::optional<uint16_t> tokenId(PdsGame game, const std::string &name) {
+  int id = rakaly_token_lookup(game, name.c_str());
+  if (id < 0) {
+    return std::nullopt;
+  }
+  return static_cast<uint16_t>(id);
+}
+
+/**
+ * Returns the field name of the given binary token id
+ */
+std::optional<std::string> tokenName(PdsGame game, uint16_t id) {
+  int len = rakaly_token_name_length(game, id);
+  if (len < 0) {
+    return std::nullopt;
+  }
+
+  std::string name(len, ' ');
+  if (rakaly_token_name(game, id, name.data(), len) != len) {
+    return std::nullopt;
+  }
+  return name;
+}
+
+/**
+ * Parses a save from any supported game, detecting the game from the data
+ */
+GameFile parseSave(const std::string &data) {
+  PdsFileResult *file_result = rakaly_file(data.c_str(), data.length());
+  unwrapError(rakaly_file_error(file_result));
+  PdsFile *file = rakaly_file_value(file_result);
+  return GameFile(file);
+}
+
+/**
+ * Opens the save at the given path, detecting the game from the data. The file
+ * is memory mapped instead of being read into memory.
+ */
+GameFile openSave(const std::string &path) {
+  PdsFileResult *file_result = rakaly_file_open_path(path.c_str());
+  unwrapError(rakaly_file_error(file_result));
+  PdsFile *file = rakaly_file_value(file_result);
+  return GameFile(file);
+}
+
 GameFile parseEu4(const std::string &data) {
   PdsFileResult *file_result = rakaly_eu4_file(data.c_str(), data.length());
   unwrapError(rakaly_file_error(file_result));
@@ -89,6 +332,13 @@ GameFile parseEu4(const std::string &data) {
   return GameFile(file);
 }
 
+GameFile openEu4(const std::string &path) {
+  PdsFileResult *file_result = rakaly_eu4_file_open_path(path.c_str());
+  unwrapError(rakaly_file_error(file_result));
+  PdsFile *file = rakaly_file_value(file_result);
+  return GameFile(file);
+}
+
 GameFile parseCk3(const std::string &data) {
   PdsFileResult *file_result = rakaly_ck3_file(data.c_str(), data.length());
   unwrapError(rakaly_file_error(file_result));
@@ -96,6 +346,13 @@ GameFile parseCk3(const std::string &data) {
   return GameFile(file);
 }
 
+GameFile openCk3(const std::string &path) {
+  PdsFileResult *file_result = rakaly_ck3_file_open_path(path.c_str());
+  unwrapError(rakaly_file_error(file_result));
+  PdsFile *file = rakaly_file_value(file_result);
+  return GameFile(file);
+}
+
 GameFile parseImperator(const std::string &data) {
   PdsFileResult *file_result =
       rakaly_imperator_file(data.c_str(), data.length());
@@ -104,6 +361,13 @@ GameFile parseImperator(const std::string &data) {
   return GameFile(file);
 }
 
+GameFile openImperator(const std::string &path) {
+  PdsFileResult *file_result = rakaly_imperator_file_open_path(path.c_str());
+  unwrapError(rakaly_file_error(file_result));
+  PdsFile *file = rakaly_file_value(file_result);
+  return GameFile(file);
+}
+
 GameFile parseHoi4(const std::string &data) {
   PdsFileResult *file_result = rakaly_hoi4_file(data.c_str(), data.length());
   unwrapError(rakaly_file_error(file_result));
@@ -111,6 +375,13 @@ GameFile parseHoi4(const std::string &data) {
   return GameFile(file);
 }
 
+GameFile openHoi4(const std::string &path) {
+  PdsFileResult *file_result = rakaly_hoi4_file_open_path(path.c_str());
+  unwrapError(rakaly_file_error(file_result));
+  PdsFile *file = rakaly_file_value(file_result);
+  return GameFile(file);
+}
+
 GameFile parseVic3(const std::string &data) {
   PdsFileResult *file_result = rakaly_vic3_file(data.c_str(), data.length());
   unwrapError(rakaly_file_error(file_result));
@@ -118,6 +389,13 @@ GameFile parseVic3(const std::string &data) {
   return GameFile(file);
 }
 
+GameFile openVic3(const std::string &path) {
+  PdsFileResult *file_result = rakaly_vic3_file_open_path(path.c_str());
+  unwrapError(rakaly_file_error(file_result));
+  PdsFile *file = rakaly_file_value(file_result);
+  return GameFile(file);
+}
+
 GameFile parseEu5(const std::string &data) {
   PdsFileResult *file_result = rakaly_eu5_file(data.c_str(), data.length());
   unwrapError(rakaly_file_error(file_result));
@@ -125,6 +403,13 @@ GameFile parseEu5(const std::string &data) {
   return GameFile(file);
 }
 
+GameFile openEu5(const std::string &path) {
+  PdsFileResult *file_result = rakaly_eu5_file_open_path(path.c_str());
+  unwrapError(rakaly_file_error(file_result));
+  PdsFile *file = rakaly_file_value(file_result);
+  return GameFile(file);
+}
+
 } // namespace rakaly
 
 #endif
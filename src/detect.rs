@@ -0,0 +1,424 @@
+//! CK3, Imperator, Vic3, and EU5 saves share the same envelope, so their
+//! parsers accept each other's saves. The games are told apart by the entries
+//! of the save's zip and the keys of its metadata, which are read without
+//! melting the save. Plaintext metadata is recognized by its keys alone, while
+//! binary metadata keys are tokens that only a game's token table can name.
+
+use crate::{
+    errors::LibError,
+    file::PdsGame,
+    tokens::{tokens_resolver, TokenTable},
+};
+use jomini::binary::TokenResolver;
+use std::collections::BTreeSet;
+
+/// The zip entry that holds the strings that EU5 binary saves reference by
+/// index. No other game writes it.
+const EU5_STRING_LOOKUP: &[u8] = b"string_lookup";
+
+/// How each game lays out its plaintext metadata: the key that wraps the
+/// metadata, if any, and keys that only that game writes within it
+const TEXT_LAYOUTS: &[(PdsGame, Option<&str>, &[&str])] = &[
+    (
+        PdsGame::Ck3,
+        Some("meta_data"),
+        &["meta_title_name", "meta_house_name", "meta_main_portrait"],
+    ),
+    (
+        PdsGame::Vic3,
+        Some("meta_data"),
+        &["game_date", "save_label", "real_date"],
+    ),
+    (PdsGame::Eu5, Some("metadata"), &[]),
+    (PdsGame::Imperator, None, &[]),
+];
+
+/// What a save's envelope reveals about the game that wrote it
+#[derive(Debug, Default)]
+pub struct Evidence {
+    /// The names of the entries in the save's zip
+    entries: Vec<Vec<u8>>,
+
+    /// The first key of plaintext metadata
+    text_root: Option<Vec<u8>>,
+
+    /// The distinct keys of plaintext metadata
+    text_keys: BTreeSet<Vec<u8>>,
+
+    /// The distinct key tokens of binary metadata
+    token_keys: BTreeSet<u16>,
+}
+
+impl Evidence {
+    /// Gathers the evidence from the save data and the metadata section of
+    /// its envelope
+    pub fn new(data: &[u8], metadata: &[u8], binary: bool) -> Self {
+        let mut evidence = Evidence {
+            entries: zip_entry_names(data),
+            ..Evidence::default()
+        };
+
+        if binary {
+            evidence.token_keys = binary_keys(metadata);
+        } else {
+            evidence.text_root = text_root(metadata);
+            evidence.text_keys = text_keys(metadata);
+        }
+
+        evidence
+    }
+}
+
+/// Returns the game among the candidates that the evidence points to, or
+/// `None` when the candidates can't be told apart
+pub fn distinguish(
+    candidates: &[PdsGame],
+    evidence: &Evidence,
+) -> Result<Option<PdsGame>, LibError> {
+    let has_lookup = evidence.entries.iter().any(|x| x == EU5_STRING_LOOKUP);
+    if has_lookup && candidates.contains(&PdsGame::Eu5) {
+        return Ok(Some(PdsGame::Eu5));
+    }
+
+    if evidence.token_keys.is_empty() {
+        return Ok(text_match(candidates, evidence));
+    }
+
+    let resolvers = candidates
+        .iter()
+        .map(|&game| tokens_resolver(game).map(|tokens| (game, tokens)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let tables = resolvers
+        .iter()
+        .map(|(game, tokens)| (*game, tokens.table()))
+        .collect::<Vec<_>>();
+
+    Ok(token_match(&tables, evidence))
+}
+
+/// Picks the game whose plaintext metadata layout the evidence follows
+fn text_match(candidates: &[PdsGame], evidence: &Evidence) -> Option<PdsGame> {
+    let root = evidence.text_root.as_deref();
+    let wrapped = TEXT_LAYOUTS
+        .iter()
+        .any(|(_, wrapper, _)| wrapper.map(str::as_bytes) == root);
+
+    let scores = TEXT_LAYOUTS
+        .iter()
+        .filter(|(game, _, _)| candidates.contains(game))
+        .filter(|(_, wrapper, _)| match wrapper {
+            Some(wrapper) => root == Some(wrapper.as_bytes()),
+            None => root.is_some() && !wrapped,
+        })
+        .map(|(game, _, signature)| {
+            let matches = signature
+                .iter()
+                .filter(|key| evidence.text_keys.contains(key.as_bytes()))
+                .count();
+            (*game, matches)
+        })
+        .collect::<Vec<_>>();
+
+    match scores.as_slice() {
+        [(game, _)] => Some(*game),
+        _ => unique_leader(&scores),
+    }
+}
+
+/// Picks the game whose tokens know the most binary metadata keys
+fn token_match(candidates: &[(PdsGame, &TokenTable)], evidence: &Evidence) -> Option<PdsGame> {
+    // A game's tokens are the vocabulary of its saves, so the metadata keys
+    // of a save are known to the game that wrote it but not necessarily to
+    // the other games.
+    let scores = candidates
+        .iter()
+        .map(|(game, table)| (*game, vocabulary_matches(table, evidence)))
+        .collect::<Vec<_>>();
+
+    unique_leader(&scores)
+}
+
+/// Returns the game with the highest score when no other game shares it
+fn unique_leader(scores: &[(PdsGame, usize)]) -> Option<PdsGame> {
+    let best = scores.iter().map(|(_, score)| *score).max()?;
+    let mut leaders = scores.iter().filter(|(_, score)| *score == best);
+    match (leaders.next(), leaders.next()) {
+        (Some((game, _)), None) if best > 0 => Some(*game),
+        _ => None,
+    }
+}
+
+/// Returns the number of binary metadata keys that are part of the game's
+/// tokens
+fn vocabulary_matches(table: &TokenTable, evidence: &Evidence) -> usize {
+    evidence
+        .token_keys
+        .iter()
+        .filter(|&&token| table.resolve(token).is_some())
+        .count()
+}
+
+/// Returns the first key of the plaintext data
+fn text_root(data: &[u8]) -> Option<Vec<u8>> {
+    use jomini::text::{Token, TokenReader};
+
+    let mut reader = TokenReader::from_slice(data);
+    match reader.next() {
+        Ok(Some(Token::Unquoted(x) | Token::Quoted(x))) => Some(x.as_bytes().to_vec()),
+        _ => None,
+    }
+}
+
+/// Returns the keys of the plaintext data
+fn text_keys(data: &[u8]) -> BTreeSet<Vec<u8>> {
+    use jomini::text::{Token, TokenReader};
+
+    let mut keys = BTreeSet::new();
+    let mut reader = TokenReader::from_slice(data);
+    let mut last: Option<Vec<u8>> = None;
+    while let Ok(Some(token)) = reader.next() {
+        match token {
+            Token::Operator(_) => keys.extend(last.take()),
+            Token::Unquoted(x) | Token::Quoted(x) => last = Some(x.as_bytes().to_vec()),
+            Token::Open | Token::Close => last = None,
+        }
+    }
+
+    keys
+}
+
+/// Returns the key tokens of the binary data
+fn binary_keys(data: &[u8]) -> BTreeSet<u16> {
+    use jomini::binary::{Token, TokenReader};
+
+    let mut keys = BTreeSet::new();
+    let mut reader = TokenReader::from_slice(data);
+    let mut last = None;
+    while let Ok(Some(token)) = reader.next() {
+        match token {
+            Token::Equal => keys.extend(last.take()),
+            Token::Id(x) => last = Some(x),
+            _ => last = None,
+        }
+    }
+
+    keys
+}
+
+/// Returns the names of the entries in the central directory of the zip at
+/// the end of the data. Data that doesn't end with a zip has no entries.
+fn zip_entry_names(data: &[u8]) -> Vec<Vec<u8>> {
+    const END_SIGNATURE: &[u8] = b"PK\x05\x06";
+    const END_LEN: usize = 22;
+    const ENTRY_SIGNATURE: &[u8] = b"PK\x01\x02";
+    const ENTRY_LEN: usize = 46;
+
+    let u16_at = |x: &[u8], at: usize| usize::from(u16::from_le_bytes([x[at], x[at + 1]]));
+    let u32_at =
+        |x: &[u8], at: usize| u32::from_le_bytes([x[at], x[at + 1], x[at + 2], x[at + 3]]) as usize;
+
+    // The end of central directory record may be followed by a comment
+    let search = data.len().saturating_sub(END_LEN + usize::from(u16::MAX));
+    let Some(end) = data[search..]
+        .windows(END_SIGNATURE.len())
+        .rposition(|x| x == END_SIGNATURE)
+        .map(|x| x + search)
+    else {
+        return Vec::new();
+    };
+
+    let Some(record) = data.get(end..end + END_LEN) else {
+        return Vec::new();
+    };
+
+    // The directory's offset is relative to the start of the zip, which is
+    // preceded by the header and metadata, so it is located by its size
+    let count = u16_at(record, 10);
+    let Some(mut pos) = end.checked_sub(u32_at(record, 12)) else {
+        return Vec::new();
+    };
+
+    let mut names = Vec::with_capacity(count);
+    for _ in 0..count {
+        let Some(entry) = data.get(pos..pos + ENTRY_LEN) else {
+            break;
+        };
+
+        if &entry[..ENTRY_SIGNATURE.len()] != ENTRY_SIGNATURE {
+            break;
+        }
+
+        let name_len = u16_at(entry, 28);
+        let extra_len = u16_at(entry, 30);
+        let comment_len = u16_at(entry, 32);
+        let Some(name) = data.get(pos + ENTRY_LEN..pos + ENTRY_LEN + name_len) else {
+            break;
+        };
+
+        names.push(name.to_vec());
+        pos += ENTRY_LEN + name_len + extra_len + comment_len;
+    }
+
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a zip central directory with the given entry names, preceded
+    /// by filler that stands in for the header, metadata, and entry data
+    fn zip_directory(names: &[&str]) -> Vec<u8> {
+        let mut data = b"SAV0103\nmeta_data={}\n".to_vec();
+        let start = data.len();
+        for name in names {
+            let mut entry = vec![0u8; 46];
+            entry[..4].copy_from_slice(b"PK\x01\x02");
+            entry[28..30].copy_from_slice(&(name.len() as u16).to_le_bytes());
+            data.extend_from_slice(&entry);
+            data.extend_from_slice(name.as_bytes());
+        }
+
+        let size = (data.len() - start) as u32;
+        let mut end = vec![0u8; 22];
+        end[..4].copy_from_slice(b"PK\x05\x06");
+        end[10..12].copy_from_slice(&(names.len() as u16).to_le_bytes());
+        end[12..16].copy_from_slice(&size.to_le_bytes());
+        data.extend_from_slice(&end);
+        data
+    }
+
+    fn evidence(entries: &[&str], text_keys: &[&str]) -> Evidence {
+        Evidence {
+            entries: entries.iter().map(|x| x.as_bytes().to_vec()).collect(),
+            text_root: text_keys.first().map(|x| x.as_bytes().to_vec()),
+            text_keys: text_keys.iter().map(|x| x.as_bytes().to_vec()).collect(),
+            token_keys: BTreeSet::new(),
+        }
+    }
+
+    fn binary_evidence(token_keys: &[u16]) -> Evidence {
+        Evidence {
+            token_keys: token_keys.iter().copied().collect(),
+            ..Evidence::default()
+        }
+    }
+
+    const SHARED_ENVELOPE: [PdsGame; 4] = [
+        PdsGame::Ck3,
+        PdsGame::Imperator,
+        PdsGame::Vic3,
+        PdsGame::Eu5,
+    ];
+
+    #[test]
+    fn reads_zip_entry_names() {
+        let data = zip_directory(&["gamestate", "string_lookup"]);
+        let names = zip_entry_names(&data);
+        assert_eq!(
+            names,
+            vec![b"gamestate".to_vec(), b"string_lookup".to_vec()]
+        );
+    }
+
+    #[test]
+    fn data_without_zip_has_no_entries() {
+        assert!(zip_entry_names(b"SAV0100\nmeta_data={}").is_empty());
+        assert!(zip_entry_names(b"").is_empty());
+        assert!(zip_entry_names(b"PK\x05\x06").is_empty());
+    }
+
+    #[test]
+    fn string_lookup_entry_is_eu5() {
+        let evidence = evidence(&["gamestate", "string_lookup"], &["meta_data"]);
+        let game = distinguish(&SHARED_ENVELOPE, &evidence).unwrap();
+        assert_eq!(game, Some(PdsGame::Eu5));
+    }
+
+    #[test]
+    fn plaintext_metadata_is_told_apart_without_tokens() {
+        let saves = [
+            (
+                PdsGame::Ck3,
+                &["meta_data", "version", "meta_title_name"][..],
+            ),
+            (PdsGame::Vic3, &["meta_data", "version", "game_date"][..]),
+            (PdsGame::Eu5, &["metadata", "version", "date"][..]),
+            (
+                PdsGame::Imperator,
+                &["version", "date", "meta_player_name"][..],
+            ),
+        ];
+
+        for (game, keys) in saves {
+            let save = evidence(&["gamestate"], keys);
+            assert_eq!(
+                text_match(&SHARED_ENVELOPE, &save),
+                Some(game),
+                "{:?}",
+                game
+            );
+        }
+    }
+
+    #[test]
+    fn plaintext_metadata_only_matches_candidates() {
+        let save = evidence(&[], &["metadata", "version"]);
+        assert_eq!(text_match(&[PdsGame::Ck3, PdsGame::Vic3], &save), None);
+
+        let save = evidence(&[], &["version", "date"]);
+        let candidates = [PdsGame::Ck3, PdsGame::Vic3, PdsGame::Eu5];
+        assert_eq!(text_match(&candidates, &save), None);
+    }
+
+    #[test]
+    fn ambiguous_plaintext_metadata_is_not_guessed() {
+        let save = evidence(&[], &["meta_data", "version"]);
+        assert_eq!(text_match(&SHARED_ENVELOPE, &save), None);
+        assert_eq!(text_match(&SHARED_ENVELOPE, &evidence(&[], &[])), None);
+    }
+
+    #[test]
+    fn binary_keys_pick_the_game_whose_tokens_know_them() {
+        let ck3 = TokenTable::parse(b"1 meta_data\n2 version\n3 meta_title_name").unwrap();
+        let vic3 = TokenTable::parse(b"1 meta_data\n2 version\n4 game_date").unwrap();
+        let candidates = [(PdsGame::Ck3, &ck3), (PdsGame::Vic3, &vic3)];
+
+        let ck3_save = binary_evidence(&[1, 2, 3]);
+        assert_eq!(token_match(&candidates, &ck3_save), Some(PdsGame::Ck3));
+
+        let vic3_save = binary_evidence(&[1, 2, 4]);
+        assert_eq!(token_match(&candidates, &vic3_save), Some(PdsGame::Vic3));
+    }
+
+    #[test]
+    fn ties_are_not_broken() {
+        let ck3 = TokenTable::parse(b"1 meta_data\n2 version").unwrap();
+        let vic3 = TokenTable::parse(b"1 meta_data\n2 version").unwrap();
+        let candidates = [(PdsGame::Ck3, &ck3), (PdsGame::Vic3, &vic3)];
+        let save = binary_evidence(&[1, 2]);
+        assert_eq!(token_match(&candidates, &save), None);
+
+        let empty = TokenTable::default();
+        let candidates = [(PdsGame::Ck3, &empty), (PdsGame::Vic3, &empty)];
+        assert_eq!(token_match(&candidates, &save), None);
+    }
+
+    #[test]
+    fn reads_the_text_root() {
+        let root = text_root(b"meta_data={\n\tversion=\"1.0\"\n}\n");
+        assert_eq!(root, Some(b"meta_data".to_vec()));
+        assert_eq!(text_root(b""), None);
+    }
+
+    #[test]
+    fn reads_text_keys() {
+        let keys = text_keys(b"meta_data={\n\tversion=\"1.0\"\n\tflag={ a b }\n}\n");
+        let expected = ["flag", "meta_data", "version"]
+            .iter()
+            .map(|x| x.as_bytes().to_vec())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(keys, expected);
+    }
+}
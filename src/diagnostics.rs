@@ -0,0 +1,74 @@
+//! Describes the context of unknown tokens in melted output, where the melter
+//! wrote each unknown token as `__unknown_0x<id>`.
+
+use jomini::{common::Date, Scalar};
+
+/// The kinds of values that an unknown token was used with. Kinds are bit
+/// flags so that every kind seen for a token can be reported together.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdsValueKind {
+    Object = 1,
+    Array = 2,
+    Text = 4,
+    Integer = 8,
+    Decimal = 16,
+    Bool = 32,
+    Date = 64,
+
+    /// The unknown token was itself a value instead of a key
+    Token = 128,
+}
+
+/// Where an unknown token was first encountered and the kinds of values it
+/// was used with
+#[derive(Debug, Clone, Default)]
+pub struct TokenContext {
+    path: String,
+    kinds: u32,
+}
+
+impl TokenContext {
+    pub fn new(path: String) -> Self {
+        TokenContext { path, kinds: 0 }
+    }
+
+    pub fn add_kind(&mut self, kind: PdsValueKind) {
+        self.kinds |= kind as u32;
+    }
+
+    /// The keys leading to the token, separated by `/`
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The `PdsValueKind` flags of the values the token was used with
+    pub fn kinds(&self) -> u32 {
+        self.kinds
+    }
+}
+
+/// Returns the id of a token that the melter wrote as `__unknown_0x<id>`
+pub fn unknown_id(data: &[u8]) -> Option<u16> {
+    let hex = data.strip_prefix(b"__unknown_0x")?;
+    let hex = std::str::from_utf8(hex).ok()?;
+    u16::from_str_radix(hex, 16).ok()
+}
+
+/// Returns the kind of an unquoted scalar
+pub fn scalar_kind(data: &[u8]) -> PdsValueKind {
+    let scalar = Scalar::new(data);
+    if unknown_id(data).is_some() {
+        PdsValueKind::Token
+    } else if scalar.to_bool().is_ok() {
+        PdsValueKind::Bool
+    } else if scalar.to_i64().is_ok() || scalar.to_u64().is_ok() {
+        PdsValueKind::Integer
+    } else if scalar.to_f64().is_ok() {
+        PdsValueKind::Decimal
+    } else if Date::parse(data).is_ok() {
+        PdsValueKind::Date
+    } else {
+        PdsValueKind::Text
+    }
+}
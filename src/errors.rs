@@ -1,5 +1,64 @@
+use crate::file::PdsGame;
+use std::{cell::RefCell, error::Error as StdError, fmt};
 use thiserror::Error;
 
+/// A stable code that categorizes an error. New codes may be added, but
+/// existing codes keep their value.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdsErrorCode {
+    /// Returned when there is no error, like when a null pointer is given
+    Unknown = 0,
+
+    /// A file could not be read or written
+    Io = 1,
+
+    /// The data does not start with a recognized save header
+    InvalidHeader = 2,
+
+    /// The zip of a compressed save is corrupt or could not be decompressed
+    Zip = 3,
+
+    /// The save's data could not be deserialized
+    Deserialize = 4,
+
+    /// A binary token could not be resolved with the `PdsFailedResolve_Error`
+    /// strategy
+    UnknownToken = 5,
+
+    /// The requested operation is not supported for the save
+    UnsupportedOperation = 6,
+
+    /// A panic occurred in librakaly
+    Panic = 7,
+
+    /// No game recognized the data
+    UnrecognizedFile = 8,
+
+    /// Token data given to librakaly is malformed
+    InvalidTokens = 9,
+
+    /// The operation requires a game to be specified
+    UnknownGame = 10,
+
+    /// Plaintext could not be parsed
+    Parse = 11,
+
+    /// An error from the save's file envelope that is not otherwise categorized
+    Envelope = 12,
+
+    /// An argument is invalid, like an enum value that is out of range
+    InvalidArgument = 13,
+
+    /// An error from a game's parser that is not otherwise categorized
+    Eu4 = 20,
+    Ck3 = 21,
+    Imperator = 22,
+    Hoi4 = 23,
+    Vic3 = 24,
+    Eu5 = 25,
+}
+
 #[derive(Error, Debug)]
 pub enum LibError {
     #[error("eu4 error: {0}")]
@@ -23,27 +82,333 @@ pub enum LibError {
     #[error("file envelope error: {0}")]
     Envelope(#[from] jomini::envelope::EnvelopeError),
 
+    #[error("parse error: {0}")]
+    Parse(#[from] jomini::Error),
+
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("invalid tokens on line {line}: {msg}")]
+    InvalidTokens { line: usize, msg: String },
+
+    #[error("a game must be specified")]
+    UnknownGame,
+
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+
     #[error("Unsupported operation: {0}")]
     UnsupportedOperation(String),
 
-    #[error("panic! Error message may be on stdout/stderr")]
-    Panic,
+    #[error("unrecognized save file: {0}")]
+    UnrecognizedFile(ParseAttempts),
+
+    #[error("the save's zip entries and metadata keys don't tell it apart from another game's")]
+    Indistinguishable,
+
+    #[error("panic: {0}")]
+    Panic(String),
+
+    #[error("{source} (at {path})")]
+    WithKeyPath { path: String, source: Box<LibError> },
+}
+
+impl LibError {
+    /// Attaches the key path that was being melted when the error occurred
+    pub fn with_key_path(self, path: Option<String>) -> LibError {
+        match path {
+            Some(path) => LibError::WithKeyPath {
+                path,
+                source: Box::new(self),
+            },
+            None => self,
+        }
+    }
+
+    /// An enum value from the caller that doesn't match any variant
+    pub fn invalid_enum(name: &str, value: u32) -> LibError {
+        LibError::InvalidArgument(format!("{} is not a valid {}", value, name))
+    }
+
+    pub fn key_path(&self) -> Option<&str> {
+        match self {
+            LibError::WithKeyPath { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The byte offset into the parsed data where the error occurred
+    pub fn offset(&self) -> Option<u64> {
+        let mut current: Option<&(dyn StdError + 'static)> = Some(self);
+        while let Some(err) = current {
+            if let Some(err) = jomini_error(err) {
+                return err.offset().map(|x| x as u64);
+            }
+            current = err.source();
+        }
+        None
+    }
+
+    pub fn code(&self) -> PdsErrorCode {
+        let fallback = match self {
+            LibError::Eu4(_) => PdsErrorCode::Eu4,
+            LibError::Ck3(_) => PdsErrorCode::Ck3,
+            LibError::Imperator(_) => PdsErrorCode::Imperator,
+            LibError::Hoi4(_) => PdsErrorCode::Hoi4,
+            LibError::Vic3(_) => PdsErrorCode::Vic3,
+            LibError::Eu5(_) => PdsErrorCode::Eu5,
+            LibError::Envelope(_) => PdsErrorCode::Envelope,
+            LibError::Parse(_) => PdsErrorCode::Parse,
+            LibError::Io(_) => PdsErrorCode::Io,
+            LibError::InvalidTokens { .. } => PdsErrorCode::InvalidTokens,
+            LibError::UnknownGame => PdsErrorCode::UnknownGame,
+            LibError::InvalidArgument(_) => PdsErrorCode::InvalidArgument,
+            LibError::UnsupportedOperation(_) => PdsErrorCode::UnsupportedOperation,
+            LibError::UnrecognizedFile(_) | LibError::Indistinguishable => {
+                PdsErrorCode::UnrecognizedFile
+            }
+            LibError::Panic(_) => PdsErrorCode::Panic,
+            LibError::WithKeyPath { source, .. } => return source.code(),
+        };
+
+        let mut current = self.source();
+        while let Some(err) = current {
+            if let Some(code) = kind_code(err) {
+                return code;
+            }
+            current = err.source();
+        }
+
+        fallback
+    }
+}
+
+/// Returns the jomini error that an error is or that the kind of a game
+/// parser's error holds, as the game parsers don't expose every jomini error
+/// as their source
+fn jomini_error(err: &(dyn StdError + 'static)) -> Option<&jomini::Error> {
+    if let Some(err) = err.downcast_ref::<jomini::Error>() {
+        Some(err)
+    } else if let Some(err) = err.downcast_ref::<eu4save::Eu4Error>() {
+        use eu4save::Eu4ErrorKind as Kind;
+        match err.kind() {
+            Kind::Parse(e) | Kind::Deserialize(e) => Some(e),
+            _ => None,
+        }
+    } else if let Some(err) = err.downcast_ref::<ck3save::Ck3Error>() {
+        use ck3save::Ck3ErrorKind as Kind;
+        match err.kind() {
+            Kind::Parse(e) | Kind::Deserialize(e) => Some(e),
+            _ => None,
+        }
+    } else if let Some(err) = err.downcast_ref::<imperator_save::ImperatorError>() {
+        use imperator_save::ImperatorErrorKind as Kind;
+        match err.kind() {
+            Kind::Parse(e) | Kind::Deserialize(e) => Some(e),
+            _ => None,
+        }
+    } else if let Some(err) = err.downcast_ref::<hoi4save::Hoi4Error>() {
+        use hoi4save::Hoi4ErrorKind as Kind;
+        match err.kind() {
+            Kind::Parse(e) | Kind::Deserialize(e) => Some(e),
+            _ => None,
+        }
+    } else if let Some(err) = err.downcast_ref::<vic3save::Vic3Error>() {
+        use vic3save::Vic3ErrorKind as Kind;
+        match err.kind() {
+            Kind::Parse(e) | Kind::Deserialize(e) => Some(e),
+            _ => None,
+        }
+    } else if let Some(err) = err.downcast_ref::<eu5save::Eu5Error>() {
+        use eu5save::Eu5ErrorKind as Kind;
+        match err.kind() {
+            Kind::Parse(e) | Kind::Deserialize(e) => Some(e),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+/// Categorizes an error from the game parsers or jomini by its kind. Kinds
+/// that don't tell the category apart return `None` so that the error's
+/// source is categorized instead.
+fn kind_code(err: &(dyn StdError + 'static)) -> Option<PdsErrorCode> {
+    if let Some(err) = err.downcast_ref::<eu4save::Eu4Error>() {
+        use eu4save::Eu4ErrorKind as Kind;
+        match err.kind() {
+            Kind::UnknownHeader => Some(PdsErrorCode::InvalidHeader),
+            Kind::UnknownToken { .. } => Some(PdsErrorCode::UnknownToken),
+            Kind::ZipCentralDirectory(_) | Kind::ZipMissingEntry | Kind::ZipBadData { .. } => {
+                Some(PdsErrorCode::Zip)
+            }
+            Kind::Deserialize(_) => Some(PdsErrorCode::Deserialize),
+            _ => None,
+        }
+    } else if let Some(err) = err.downcast_ref::<ck3save::Ck3Error>() {
+        use ck3save::Ck3ErrorKind as Kind;
+        match err.kind() {
+            Kind::UnknownToken { .. } => Some(PdsErrorCode::UnknownToken),
+            Kind::Deserialize(_) => Some(PdsErrorCode::Deserialize),
+            _ => None,
+        }
+    } else if let Some(err) = err.downcast_ref::<imperator_save::ImperatorError>() {
+        use imperator_save::ImperatorErrorKind as Kind;
+        match err.kind() {
+            Kind::UnknownToken { .. } => Some(PdsErrorCode::UnknownToken),
+            Kind::Deserialize(_) => Some(PdsErrorCode::Deserialize),
+            _ => None,
+        }
+    } else if let Some(err) = err.downcast_ref::<hoi4save::Hoi4Error>() {
+        use hoi4save::Hoi4ErrorKind as Kind;
+        match err.kind() {
+            Kind::UnknownHeader => Some(PdsErrorCode::InvalidHeader),
+            Kind::UnknownToken { .. } => Some(PdsErrorCode::UnknownToken),
+            Kind::Deserialize(_) => Some(PdsErrorCode::Deserialize),
+            _ => None,
+        }
+    } else if let Some(err) = err.downcast_ref::<vic3save::Vic3Error>() {
+        use vic3save::Vic3ErrorKind as Kind;
+        match err.kind() {
+            Kind::UnknownToken { .. } => Some(PdsErrorCode::UnknownToken),
+            Kind::Deserialize(_) => Some(PdsErrorCode::Deserialize),
+            _ => None,
+        }
+    } else if let Some(err) = err.downcast_ref::<eu5save::Eu5Error>() {
+        use eu5save::Eu5ErrorKind as Kind;
+        match err.kind() {
+            Kind::UnknownToken { .. } => Some(PdsErrorCode::UnknownToken),
+            Kind::Deserialize(_) => Some(PdsErrorCode::Deserialize),
+            _ => None,
+        }
+    } else if let Some(err) = err.downcast_ref::<jomini::envelope::EnvelopeError>() {
+        use jomini::envelope::EnvelopeErrorKind as Kind;
+        match err.kind() {
+            Kind::InvalidHeader => Some(PdsErrorCode::InvalidHeader),
+            Kind::Zip(_) | Kind::Decompression(_) => Some(PdsErrorCode::Zip),
+            _ => None,
+        }
+    } else if let Some(err) = err.downcast_ref::<jomini::Error>() {
+        match err.kind() {
+            jomini::ErrorKind::Deserialize(_) => Some(PdsErrorCode::Deserialize),
+            _ => Some(PdsErrorCode::Parse),
+        }
+    } else if err.is::<std::io::Error>() {
+        Some(PdsErrorCode::Io)
+    } else {
+        None
+    }
+}
+
+/// The reasons each game's parser rejected a file
+#[derive(Debug)]
+pub struct ParseAttempts(Vec<(PdsGame, LibError)>);
+
+impl From<Vec<(PdsGame, LibError)>> for ParseAttempts {
+    fn from(value: Vec<(PdsGame, LibError)>) -> Self {
+        ParseAttempts(value)
+    }
+}
+
+impl fmt::Display for ParseAttempts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (game, err)) in self.0.iter().enumerate() {
+            if i != 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{} parser rejected file ({})", game, err)?;
+        }
+        Ok(())
+    }
 }
 
 pub struct PdsError {
     msg: String,
+    code: PdsErrorCode,
+    offset: Option<u64>,
+    key_path: Option<String>,
 }
 
 impl PdsError {
     pub fn msg(&self) -> &str {
         self.msg.as_str()
     }
+
+    pub fn code(&self) -> PdsErrorCode {
+        self.code
+    }
+
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    pub fn key_path(&self) -> Option<&str> {
+        self.key_path.as_deref()
+    }
 }
 
 impl<'a> From<&'a LibError> for PdsError {
     fn from(value: &'a LibError) -> Self {
         PdsError {
             msg: value.to_string(),
+            code: value.code(),
+            offset: value.offset(),
+            key_path: value.key_path().map(String::from),
         }
     }
 }
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<PdsError>> = const { RefCell::new(None) };
+}
+
+/// Records an error as the calling thread's last error before it is returned
+/// to the caller
+pub fn record_last_error(err: LibError) -> LibError {
+    let _ = LAST_ERROR.try_with(|x| *x.borrow_mut() = Some(PdsError::from(&err)));
+    err
+}
+
+/// Takes the calling thread's last error
+pub fn take_last_error() -> Option<PdsError> {
+    LAST_ERROR
+        .try_with(|x| x.borrow_mut().take())
+        .ok()
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_follow_the_kind_of_the_error() {
+        let err = LibError::from(jomini::TextTape::from_slice(b"a={ b=c").unwrap_err());
+        assert_eq!(err.code(), PdsErrorCode::Parse);
+
+        let err = LibError::from(std::io::Error::other("the header is missing from the zip"));
+        assert_eq!(err.code(), PdsErrorCode::Io);
+
+        let err = LibError::UnsupportedOperation(String::from("melting a zip"));
+        assert_eq!(err.code(), PdsErrorCode::UnsupportedOperation);
+
+        let err = LibError::WithKeyPath {
+            path: String::from("provinces"),
+            source: Box::new(LibError::InvalidArgument(String::from("magic"))),
+        };
+        assert_eq!(err.code(), PdsErrorCode::InvalidArgument);
+    }
+
+    #[test]
+    fn offset_and_key_path_of_a_parse_error() {
+        let err = LibError::from(jomini::TextTape::from_slice(b"a=b\n}").unwrap_err());
+        assert!(err.offset().is_some());
+        assert_eq!(err.key_path(), None);
+
+        let err = err.with_key_path(Some(String::from("provinces/-1")));
+        assert!(err.offset().is_some());
+        assert_eq!(err.key_path(), Some("provinces/-1"));
+        assert_eq!(PdsError::from(&err).key_path(), Some("provinces/-1"));
+        assert_eq!(err.with_key_path(None).code(), PdsErrorCode::Parse);
+    }
+}
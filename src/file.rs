@@ -1,24 +1,117 @@
-use std::io::Cursor;
+use std::{
+    convert::TryFrom,
+    fmt,
+    io::{Cursor, Write},
+    path::Path,
+    sync::OnceLock,
+    time::Instant,
+};
 
 use crate::{
+    detect::{self, Evidence},
     errors::LibError,
-    melter::Melter,
+    header::{binary_header, text_header},
+    log,
+    melter::{log_melt, IronmanFilter, MeltOutcome, Melter},
+    options::MeltOptions,
+    summary::PdsSummary,
     tokens::{
-        ck3_tokens_resolver, eu4_tokens_resolver, eu5_tokens_resolver, imperator_tokens_resolver,
-        vic3_tokens_resolver,
+        ck3_tokens_resolver, eu4_tokens_resolver, eu5_tokens_resolver, hoi4_tokens_resolver,
+        imperator_tokens_resolver, vic3_tokens_resolver,
     },
+    tracker::{self, MeltTracker},
     MeltedBuffer,
 };
 use eu4save::file::{Eu4SliceFile, Eu4Zip};
 use eu5save::{JominiFileKind, SaveDataKind};
 use hoi4save::file::Hoi4SliceFile;
+use jomini::binary::TokenResolver;
+
+/// The game that a save file belongs to
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdsGame {
+    /// Returned when the game can not be determined, like when a null pointer
+    /// is given
+    Unknown = 0,
+    Eu4 = 1,
+    Ck3 = 2,
+    Imperator = 3,
+    Hoi4 = 4,
+    Vic3 = 5,
+    Eu5 = 6,
+}
+
+impl TryFrom<u32> for PdsGame {
+    type Error = LibError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(PdsGame::Unknown),
+            1 => Ok(PdsGame::Eu4),
+            2 => Ok(PdsGame::Ck3),
+            3 => Ok(PdsGame::Imperator),
+            4 => Ok(PdsGame::Hoi4),
+            5 => Ok(PdsGame::Vic3),
+            6 => Ok(PdsGame::Eu5),
+            _ => Err(LibError::invalid_enum("PdsGame", value)),
+        }
+    }
+}
+
+impl fmt::Display for PdsGame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PdsGame::Unknown => "unknown",
+            PdsGame::Eu4 => "eu4",
+            PdsGame::Ck3 => "ck3",
+            PdsGame::Imperator => "imperator",
+            PdsGame::Hoi4 => "hoi4",
+            PdsGame::Vic3 => "vic3",
+            PdsGame::Eu5 => "eu5",
+        };
+        f.write_str(name)
+    }
+}
+
+/// How a save file is encoded
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdsEncoding {
+    /// Returned when the encoding can not be determined, like when a null
+    /// pointer is given
+    Unknown = 0,
+
+    /// Uncompressed plaintext, like a save written in debug mode
+    Text = 1,
+
+    /// Uncompressed binary
+    Binary = 2,
+
+    /// Compressed plaintext
+    TextZip = 3,
+
+    /// Compressed binary, like an ironman save
+    BinaryZip = 4,
+}
 
 pub enum PdsFileResult<'a> {
     Ok(PdsFile<'a>),
     Err(LibError),
 }
 
-pub enum PdsFile<'a> {
+/// An opaque struct that holds a parsed save file
+pub struct PdsFile<'a> {
+    save: SaveFile<'a>,
+    data: &'a [u8],
+    header: OnceLock<Option<Vec<u8>>>,
+
+    // Declared after `save` so that the save is dropped before the data that
+    // it borrows
+    _owned: Option<OwnedData>,
+}
+
+pub enum SaveFile<'a> {
     Eu4(Eu4SliceFile<'a>),
     Ck3(jomini::envelope::JominiFile<Cursor<&'a [u8]>>),
     Imperator(jomini::envelope::JominiFile<Cursor<&'a [u8]>>),
@@ -28,177 +121,313 @@ pub enum PdsFile<'a> {
 }
 
 impl PdsFile<'_> {
-    pub(crate) fn meta(&self) -> Option<PdsMeta<'_>> {
-        match self {
-            PdsFile::Eu4(file) => {
-                let eu4save::file::Eu4SliceFileKind::Zip(zip) = file.kind() else {
-                    return None;
-                };
+    /// Melts the save into the given writer. Unlike `melt_to`, verbatim saves
+    /// are copied to the writer so that the output is always complete.
+    pub(crate) fn melt_all_to<W: Write>(
+        &self,
+        options: &MeltOptions,
+        mut output: W,
+    ) -> Result<MeltOutcome, LibError> {
+        let outcome = self.melt_to(options, &mut output)?;
+        if matches!(outcome, MeltOutcome::Verbatim) {
+            output.write_all(self.data)?;
+        }
+        output.flush()?;
+        Ok(outcome)
+    }
+
+    pub(crate) fn game(&self) -> PdsGame {
+        match &self.save {
+            SaveFile::Eu4(_) => PdsGame::Eu4,
+            SaveFile::Ck3(_) => PdsGame::Ck3,
+            SaveFile::Imperator(_) => PdsGame::Imperator,
+            SaveFile::Hoi4(_) => PdsGame::Hoi4,
+            SaveFile::Vic3(_) => PdsGame::Vic3,
+            SaveFile::Eu5(_) => PdsGame::Eu5,
+        }
+    }
+
+    /// The metadata of the save, if it has easily extractable metadata
+    pub(crate) fn meta(&self) -> Result<Option<PdsMeta<'_>>, LibError> {
+        // The metadata of uncompressed saves that share the jomini envelope
+        // is the section after the header line, which is parsed on its own
+        // so that the gamestate isn't mistaken for metadata
+        let jomini_meta = match &self.save {
+            SaveFile::Ck3(file)
+            | SaveFile::Imperator(file)
+            | SaveFile::Vic3(file)
+            | SaveFile::Eu5(file) => jomini_meta(self.data, file),
+            SaveFile::Eu4(_) | SaveFile::Hoi4(_) => None,
+        };
 
-                Some(PdsMeta::Eu4(zip.clone()))
+        let meta = match (&self.save, jomini_meta) {
+            (SaveFile::Eu4(file), _) => match file.kind() {
+                eu4save::file::Eu4SliceFileKind::Zip(zip) => SaveMeta::Eu4(zip.clone()),
+                _ => match self.header() {
+                    Some(header) => SaveMeta::Eu4Header(header),
+                    None => return Ok(None),
+                },
+            },
+            (SaveFile::Hoi4(_), _) => match self.header() {
+                Some(header) => SaveMeta::Hoi4Header(header),
+                None => return Ok(None),
+            },
+            (SaveFile::Ck3(_), Some(data)) => SaveMeta::Ck3(ck3save::Ck3File::from_slice(data)?),
+            (SaveFile::Imperator(_), Some(data)) => {
+                SaveMeta::Imperator(imperator_save::ImperatorFile::from_slice(data)?)
             }
-            PdsFile::Ck3(file) => Some(PdsMeta::Ck3(file.clone())),
-            PdsFile::Imperator(file) => Some(PdsMeta::Imperator(file.clone())),
-            PdsFile::Hoi4(_) => None,
-            PdsFile::Vic3(file) => Some(PdsMeta::Vic3(file.clone())),
-            PdsFile::Eu5(file) => Some(PdsMeta::Eu5(file.clone())),
+            (SaveFile::Vic3(_), Some(data)) => {
+                SaveMeta::Vic3(vic3save::Vic3File::from_slice(data)?)
+            }
+            (SaveFile::Eu5(_), Some(data)) => SaveMeta::Eu5(eu5save::Eu5File::from_slice(data)?),
+            (SaveFile::Ck3(file), None) => SaveMeta::Ck3(file.clone()),
+            (SaveFile::Imperator(file), None) => SaveMeta::Imperator(file.clone()),
+            (SaveFile::Vic3(file), None) => SaveMeta::Vic3(file.clone()),
+            (SaveFile::Eu5(file), None) => SaveMeta::Eu5(file.clone()),
+        };
+
+        Ok(Some(PdsMeta {
+            meta,
+            data: jomini_meta.unwrap_or(self.data),
+        }))
+    }
+
+    /// The header of an uncompressed save, which is synthesized once from the
+    /// header fields as it requires scanning the save and looking up tokens
+    fn header(&self) -> Option<&[u8]> {
+        self.header
+            .get_or_init(|| self.synthesize_header())
+            .as_deref()
+    }
+
+    fn synthesize_header(&self) -> Option<Vec<u8>> {
+        match &self.save {
+            // Uncompressed saves start with the same fields that are found in
+            // the metadata entry of compressed saves, though fields that aren't
+            // part of the metadata may come between them
+            SaveFile::Eu4(file) => match file.kind() {
+                eu4save::file::Eu4SliceFileKind::Text(_) => {
+                    let body = self.data.strip_prefix(b"EU4txt")?;
+                    let fields = text_header(body, EU4_META_FIELDS, EU4_HEADER_EXTENT);
+                    Some([b"EU4txt\n", fields.as_slice()].concat())
+                }
+                eu4save::file::Eu4SliceFileKind::Binary(_) => {
+                    let body = self.data.strip_prefix(b"EU4bin")?;
+                    let tokens = eu4_tokens_resolver().ok()?;
+                    let fields =
+                        binary_header(body, EU4_META_FIELDS, tokens.table(), EU4_HEADER_EXTENT);
+                    Some([b"EU4bin", fields.as_slice()].concat())
+                }
+                eu4save::file::Eu4SliceFileKind::Zip(_) => None,
+            },
+
+            // The header fields are among the leading top level fields,
+            // though objects may come before some of them
+            SaveFile::Hoi4(file) => match file.encoding() {
+                hoi4save::Encoding::Plaintext => {
+                    let body = self.data.strip_prefix(b"HOI4txt")?;
+                    let fields = text_header(body, HOI4_META_FIELDS, HOI4_HEADER_EXTENT);
+                    Some([b"HOI4txt\n", fields.as_slice()].concat())
+                }
+                hoi4save::Encoding::Binary => {
+                    let body = self.data.strip_prefix(b"HOI4bin")?;
+                    let tokens = hoi4_tokens_resolver().ok()?;
+                    let fields =
+                        binary_header(body, HOI4_META_FIELDS, tokens.table(), HOI4_HEADER_EXTENT);
+                    Some([b"HOI4bin", fields.as_slice()].concat())
+                }
+            },
+            _ => None,
         }
     }
 
-    pub(crate) fn melt_file(&self) -> Result<MeltedBuffer, LibError> {
-        match self {
-            PdsFile::Eu4(file) => Melter::melt(file),
-            PdsFile::Hoi4(file) => Melter::melt(file),
+    /// Extracts commonly needed fields from the save's metadata. Saves without
+    /// easily extractable metadata are an error.
+    pub(crate) fn summary(&self) -> Result<PdsSummary, LibError> {
+        let meta = self.meta()?.ok_or_else(|| {
+            LibError::UnsupportedOperation(String::from("summarizing a save without metadata"))
+        })?;
+
+        let mut melted = Vec::new();
+        meta.melt_all_to(&MeltOptions::default(), &mut melted)?;
+        PdsSummary::from_melted(self.game(), &melted)
+    }
+
+    pub(crate) fn melt_file(&self, options: &MeltOptions) -> Result<MeltedBuffer, LibError> {
+        let mut output = Vec::new();
+        let outcome = self
+            .melt_to(options, &mut output)
+            .map_err(|e| with_output_key_path(e, &output))?;
+        Ok(outcome.into_buffer(output))
+    }
+
+    /// Melts the save into the given writer. Verbatim saves are not written,
+    /// see `melt_all_to` for that.
+    pub(crate) fn melt_to<W: Write>(
+        &self,
+        options: &MeltOptions,
+        output: W,
+    ) -> Result<MeltOutcome, LibError> {
+        log::debug(format_args!(
+            "melting {} save encoded as {:?}",
+            self.game(),
+            self.encoding()
+        ));
+        options.validate()?;
+        let start = Instant::now();
+        let mut output = IronmanFilter::new(output, options.strip_ironman());
+        let outcome = self.melt_save_to(options, &mut output)?;
+        output.finish()?;
+        log_melt(self.game(), "save", &outcome, start.elapsed());
+        Ok(outcome.diagnose(options))
+    }
+
+    fn melt_save_to<W: Write>(
+        &self,
+        options: &MeltOptions,
+        mut output: W,
+    ) -> Result<MeltOutcome, LibError> {
+        match &self.save {
+            SaveFile::Eu4(file) => Melter::melt(file, options, output),
+            SaveFile::Hoi4(file) => Melter::melt(file, options, output),
 
-            PdsFile::Ck3(file) => match file.kind() {
-                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => Ok(MeltedBuffer::Verbatim),
+            SaveFile::Ck3(file) => match file.kind() {
+                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => Ok(MeltOutcome::Verbatim),
                 JominiFileKind::Uncompressed(SaveDataKind::Binary(binary)) => {
-                    let options = ck3save::MeltOptions::new()
-                        .verbatim(true)
-                        .on_failed_resolve(ck3save::FailedResolveStrategy::Stringify);
-                    let mut output = Cursor::new(Vec::new());
+                    let mut tracker = MeltTracker::new(&mut output, options);
+                    let options = options.ck3();
                     let doc = ck3save::Ck3Melt::melt(
                         &mut &*binary,
                         options,
-                        ck3_tokens_resolver(),
-                        &mut output,
-                    )?;
-                    Ok(MeltedBuffer::Binary {
-                        body: output.into_inner(),
-                        unknown_tokens: !doc.unknown_tokens().is_empty(),
+                        tracker.resolver(ck3_tokens_resolver()?),
+                        &mut tracker,
+                    );
+                    let doc = tracker.with_key_path(doc)?;
+                    Ok(MeltOutcome::Binary {
+                        unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
                     })
                 }
                 JominiFileKind::Zip(zip) => {
-                    let options = ck3save::MeltOptions::new()
-                        .verbatim(true)
-                        .on_failed_resolve(ck3save::FailedResolveStrategy::Stringify);
-                    let mut output = Cursor::new(Vec::new());
+                    let mut tracker = MeltTracker::new(&mut output, options);
+                    let options = options.ck3();
+                    log::debug(format_args!("reading the gamestate entry from the ck3 zip"));
                     let doc = ck3save::Ck3Melt::melt(
                         &mut &*zip,
                         options,
-                        ck3_tokens_resolver(),
-                        &mut output,
-                    )?;
+                        tracker.resolver(ck3_tokens_resolver()?),
+                        &mut tracker,
+                    );
+                    let doc = tracker.with_key_path(doc)?;
                     if file.header().kind().is_text() {
-                        Ok(MeltedBuffer::Text {
-                            header: Vec::new(),
-                            body: output.into_inner(),
-                        })
+                        Ok(MeltOutcome::Text)
                     } else {
-                        Ok(MeltedBuffer::Binary {
-                            body: output.into_inner(),
-                            unknown_tokens: !doc.unknown_tokens().is_empty(),
+                        Ok(MeltOutcome::Binary {
+                            unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
                         })
                     }
                 }
             },
-            PdsFile::Imperator(file) => match file.kind() {
-                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => Ok(MeltedBuffer::Verbatim),
+            SaveFile::Imperator(file) => match file.kind() {
+                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => Ok(MeltOutcome::Verbatim),
                 JominiFileKind::Uncompressed(SaveDataKind::Binary(binary)) => {
-                    let options = imperator_save::MeltOptions::new()
-                        .verbatim(true)
-                        .on_failed_resolve(imperator_save::FailedResolveStrategy::Stringify);
-                    let mut output = Cursor::new(Vec::new());
+                    let mut tracker = MeltTracker::new(&mut output, options);
+                    let options = options.imperator();
                     let doc = imperator_save::ImperatorMelt::melt(
                         &mut &*binary,
                         options,
-                        imperator_tokens_resolver(),
-                        &mut output,
-                    )?;
-                    Ok(MeltedBuffer::Binary {
-                        body: output.into_inner(),
-                        unknown_tokens: !doc.unknown_tokens().is_empty(),
+                        tracker.resolver(imperator_tokens_resolver()?),
+                        &mut tracker,
+                    );
+                    let doc = tracker.with_key_path(doc)?;
+                    Ok(MeltOutcome::Binary {
+                        unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
                     })
                 }
                 JominiFileKind::Zip(zip) => {
-                    let options = imperator_save::MeltOptions::new()
-                        .verbatim(true)
-                        .on_failed_resolve(imperator_save::FailedResolveStrategy::Stringify);
-                    let mut output = Cursor::new(Vec::new());
+                    let mut tracker = MeltTracker::new(&mut output, options);
+                    let options = options.imperator();
+                    log::debug(format_args!(
+                        "reading the gamestate entry from the imperator zip"
+                    ));
                     let doc = imperator_save::ImperatorMelt::melt(
                         &mut &*zip,
                         options,
-                        imperator_tokens_resolver(),
-                        &mut output,
-                    )?;
+                        tracker.resolver(imperator_tokens_resolver()?),
+                        &mut tracker,
+                    );
+                    let doc = tracker.with_key_path(doc)?;
                     if file.header().kind().is_text() {
-                        Ok(MeltedBuffer::Text {
-                            header: Vec::new(),
-                            body: output.into_inner(),
-                        })
+                        Ok(MeltOutcome::Text)
                     } else {
-                        Ok(MeltedBuffer::Binary {
-                            body: output.into_inner(),
-                            unknown_tokens: !doc.unknown_tokens().is_empty(),
+                        Ok(MeltOutcome::Binary {
+                            unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
                         })
                     }
                 }
             },
 
-            PdsFile::Vic3(file) => match file.kind() {
-                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => Ok(MeltedBuffer::Verbatim),
+            SaveFile::Vic3(file) => match file.kind() {
+                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => Ok(MeltOutcome::Verbatim),
                 JominiFileKind::Uncompressed(SaveDataKind::Binary(binary)) => {
-                    let options = vic3save::MeltOptions::new()
-                        .verbatim(true)
-                        .on_failed_resolve(vic3save::FailedResolveStrategy::Stringify);
-                    let mut output = Cursor::new(Vec::new());
+                    let mut tracker = MeltTracker::new(&mut output, options);
+                    let options = options.vic3();
                     let doc = vic3save::Vic3Melt::melt(
                         &mut &*binary,
                         options,
-                        vic3_tokens_resolver(),
-                        &mut output,
-                    )?;
-                    Ok(MeltedBuffer::Binary {
-                        body: output.into_inner(),
-                        unknown_tokens: !doc.unknown_tokens().is_empty(),
+                        tracker.resolver(vic3_tokens_resolver()?),
+                        &mut tracker,
+                    );
+                    let doc = tracker.with_key_path(doc)?;
+                    Ok(MeltOutcome::Binary {
+                        unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
                     })
                 }
                 JominiFileKind::Zip(zip) => {
-                    let options = vic3save::MeltOptions::new()
-                        .verbatim(true)
-                        .on_failed_resolve(vic3save::FailedResolveStrategy::Stringify);
-                    let mut output = Cursor::new(Vec::new());
+                    let mut tracker = MeltTracker::new(&mut output, options);
+                    let options = options.vic3();
+                    log::debug(format_args!(
+                        "reading the gamestate entry from the vic3 zip"
+                    ));
                     let doc = vic3save::Vic3Melt::melt(
                         &mut &*zip,
                         options,
-                        vic3_tokens_resolver(),
-                        &mut output,
-                    )?;
+                        tracker.resolver(vic3_tokens_resolver()?),
+                        &mut tracker,
+                    );
+                    let doc = tracker.with_key_path(doc)?;
                     if file.header().kind().is_text() {
-                        Ok(MeltedBuffer::Text {
-                            header: Vec::new(),
-                            body: output.into_inner(),
-                        })
+                        Ok(MeltOutcome::Text)
                     } else {
-                        Ok(MeltedBuffer::Binary {
-                            body: output.into_inner(),
-                            unknown_tokens: !doc.unknown_tokens().is_empty(),
+                        Ok(MeltOutcome::Binary {
+                            unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
                         })
                     }
                 }
             },
 
-            PdsFile::Eu5(file) => match file.kind() {
-                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => Ok(MeltedBuffer::Verbatim),
+            SaveFile::Eu5(file) => match file.kind() {
+                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => Ok(MeltOutcome::Verbatim),
                 JominiFileKind::Uncompressed(SaveDataKind::Binary(_)) => Err(
                     LibError::UnsupportedOperation(String::from("melting uncompressed eu5 binary")),
                 ),
                 JominiFileKind::Zip(zip) => {
-                    let options = eu5save::MeltOptions::new()
-                        .verbatim(true)
-                        .on_failed_resolve(eu5save::FailedResolveStrategy::Stringify);
-                    let mut output = Cursor::new(Vec::new());
-                    let resolver = eu5save::SaveResolver::create(zip, eu5_tokens_resolver())?;
-                    let doc = eu5save::Eu5Melt::melt(&mut &*zip, options, resolver, &mut output)?;
+                    let mut tracker = MeltTracker::new(&mut output, options);
+                    let options = options.eu5();
+                    log::debug(format_args!(
+                        "reading the string lookup entry from the eu5 zip"
+                    ));
+                    let resolver = eu5save::SaveResolver::create(
+                        zip,
+                        tracker.resolver(eu5_tokens_resolver()?),
+                    )?;
+                    log::debug(format_args!("reading the gamestate entry from the eu5 zip"));
+                    let doc = eu5save::Eu5Melt::melt(&mut &*zip, options, resolver, &mut tracker);
+                    let doc = tracker.with_key_path(doc)?;
                     if file.header().kind().is_text() {
-                        Ok(MeltedBuffer::Text {
-                            header: Vec::new(),
-                            body: output.into_inner(),
-                        })
+                        Ok(MeltOutcome::Text)
                     } else {
-                        Ok(MeltedBuffer::Binary {
-                            body: output.into_inner(),
-                            unknown_tokens: !doc.unknown_tokens().is_empty(),
+                        Ok(MeltOutcome::Binary {
+                            unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
                         })
                     }
                 }
@@ -206,200 +435,286 @@ impl PdsFile<'_> {
         }
     }
 
+    pub(crate) fn encoding(&self) -> PdsEncoding {
+        match &self.save {
+            SaveFile::Eu4(file) => match file.encoding() {
+                eu4save::Encoding::Text => PdsEncoding::Text,
+                eu4save::Encoding::Binary => PdsEncoding::Binary,
+                eu4save::Encoding::TextZip => PdsEncoding::TextZip,
+                eu4save::Encoding::BinaryZip => PdsEncoding::BinaryZip,
+            },
+            SaveFile::Ck3(file)
+            | SaveFile::Imperator(file)
+            | SaveFile::Eu5(file)
+            | SaveFile::Vic3(file) => match file.kind() {
+                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => PdsEncoding::Text,
+                JominiFileKind::Uncompressed(SaveDataKind::Binary(_)) => PdsEncoding::Binary,
+                JominiFileKind::Zip(_) if file.header().kind().is_text() => PdsEncoding::TextZip,
+                JominiFileKind::Zip(_) => PdsEncoding::BinaryZip,
+            },
+            SaveFile::Hoi4(file) => match file.encoding() {
+                hoi4save::Encoding::Plaintext => PdsEncoding::Text,
+                hoi4save::Encoding::Binary => PdsEncoding::Binary,
+            },
+        }
+    }
+
     pub(crate) fn is_binary(&self) -> bool {
-        match self {
-            PdsFile::Eu4(file) => file.encoding().is_binary(),
-            PdsFile::Ck3(file)
-            | PdsFile::Imperator(file)
-            | PdsFile::Eu5(file)
-            | PdsFile::Vic3(file) => file.header().kind().is_binary(),
-            PdsFile::Hoi4(file) => matches!(file.encoding(), hoi4save::Encoding::Binary),
+        match &self.save {
+            SaveFile::Eu4(file) => file.encoding().is_binary(),
+            SaveFile::Ck3(file)
+            | SaveFile::Imperator(file)
+            | SaveFile::Eu5(file)
+            | SaveFile::Vic3(file) => file.header().kind().is_binary(),
+            SaveFile::Hoi4(file) => matches!(file.encoding(), hoi4save::Encoding::Binary),
         }
     }
 }
 
-pub enum PdsMeta<'data> {
+/// An opaque struct that holds the metadata of a save file
+pub struct PdsMeta<'data> {
+    meta: SaveMeta<'data>,
+    data: &'data [u8],
+}
+
+pub enum SaveMeta<'data> {
     Eu4(Box<Eu4Zip<&'data [u8]>>),
     Ck3(jomini::envelope::JominiFile<Cursor<&'data [u8]>>),
     Imperator(jomini::envelope::JominiFile<Cursor<&'data [u8]>>),
     Vic3(jomini::envelope::JominiFile<Cursor<&'data [u8]>>),
     Eu5(jomini::envelope::JominiFile<Cursor<&'data [u8]>>),
+
+    /// The magic of an uncompressed save followed by its metadata fields
+    Eu4Header(&'data [u8]),
+
+    /// The magic of a save followed by its header fields
+    Hoi4Header(&'data [u8]),
 }
 
 impl PdsMeta<'_> {
-    pub(crate) fn melt(&self) -> Result<MeltedBuffer, LibError> {
-        match self {
-            PdsMeta::Eu4(entry) => {
-                let options = eu4save::MeltOptions::new()
-                    .verbatim(true)
-                    .on_failed_resolve(eu4save::FailedResolveStrategy::Stringify);
-                let resolver = eu4_tokens_resolver();
-                let mut output = Cursor::new(Vec::new());
-                let doc = entry.melt(options, resolver, &mut output)?;
+    pub(crate) fn game(&self) -> PdsGame {
+        match &self.meta {
+            SaveMeta::Eu4(_) | SaveMeta::Eu4Header(_) => PdsGame::Eu4,
+            SaveMeta::Ck3(_) => PdsGame::Ck3,
+            SaveMeta::Imperator(_) => PdsGame::Imperator,
+            SaveMeta::Hoi4Header(_) => PdsGame::Hoi4,
+            SaveMeta::Vic3(_) => PdsGame::Vic3,
+            SaveMeta::Eu5(_) => PdsGame::Eu5,
+        }
+    }
+
+    pub(crate) fn melt(&self, options: &MeltOptions) -> Result<MeltedBuffer, LibError> {
+        let mut output = Vec::new();
+        let outcome = self
+            .melt_to(options, &mut output)
+            .map_err(|e| with_output_key_path(e, &output))?;
+        Ok(outcome.into_buffer(output))
+    }
+
+    /// Melts the metadata into the given writer. Unlike `melt_to`, verbatim
+    /// saves are copied to the writer so that the output is always complete.
+    pub(crate) fn melt_all_to<W: Write>(
+        &self,
+        options: &MeltOptions,
+        mut output: W,
+    ) -> Result<MeltOutcome, LibError> {
+        let outcome = self.melt_to(options, &mut output)?;
+        if matches!(outcome, MeltOutcome::Verbatim) {
+            output.write_all(self.data)?;
+        }
+        output.flush()?;
+        Ok(outcome)
+    }
+
+    pub(crate) fn melt_to<W: Write>(
+        &self,
+        options: &MeltOptions,
+        output: W,
+    ) -> Result<MeltOutcome, LibError> {
+        options.validate()?;
+        let start = Instant::now();
+        let mut output = IronmanFilter::new(output, options.strip_ironman());
+        let outcome = self.melt_meta_to(options, &mut output)?;
+        output.finish()?;
+        log_melt(self.game(), "metadata", &outcome, start.elapsed());
+        Ok(outcome.diagnose(options))
+    }
+
+    fn melt_meta_to<W: Write>(
+        &self,
+        options: &MeltOptions,
+        mut output: W,
+    ) -> Result<MeltOutcome, LibError> {
+        match &self.meta {
+            SaveMeta::Eu4(entry) => {
+                let mut tracker = MeltTracker::new(&mut output, options);
+                let options = options.eu4();
+                let resolver = tracker.resolver(eu4_tokens_resolver()?);
+                let doc = entry.melt(options, resolver, &mut tracker);
+                let doc = tracker.with_key_path(doc)?;
                 if entry.encoding().is_text() {
-                    Ok(MeltedBuffer::Text {
-                        header: Vec::new(),
-                        body: output.into_inner(),
-                    })
+                    Ok(MeltOutcome::Text)
                 } else {
-                    Ok(MeltedBuffer::Binary {
-                        body: output.into_inner(),
-                        unknown_tokens: !doc.unknown_tokens().is_empty(),
+                    Ok(MeltOutcome::Binary {
+                        unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
                     })
                 }
             }
-            PdsMeta::Ck3(file) => match file.kind() {
-                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => Ok(MeltedBuffer::Verbatim),
+            SaveMeta::Ck3(file) => match file.kind() {
+                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => Ok(MeltOutcome::Verbatim),
                 JominiFileKind::Uncompressed(SaveDataKind::Binary(binary)) => {
-                    let options = ck3save::MeltOptions::new()
-                        .verbatim(true)
-                        .on_failed_resolve(ck3save::FailedResolveStrategy::Stringify);
-                    let mut output = Cursor::new(Vec::new());
+                    let mut tracker = MeltTracker::new(&mut output, options);
+                    let options = options.ck3();
                     let doc = ck3save::Ck3Melt::melt(
                         &mut &*binary,
                         options,
-                        ck3_tokens_resolver(),
-                        &mut output,
-                    )?;
-                    Ok(MeltedBuffer::Binary {
-                        body: output.into_inner(),
-                        unknown_tokens: !doc.unknown_tokens().is_empty(),
+                        tracker.resolver(ck3_tokens_resolver()?),
+                        &mut tracker,
+                    );
+                    let doc = tracker.with_key_path(doc)?;
+                    Ok(MeltOutcome::Binary {
+                        unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
                     })
                 }
                 JominiFileKind::Zip(zip) => {
-                    let options = ck3save::MeltOptions::new()
-                        .verbatim(true)
-                        .on_failed_resolve(ck3save::FailedResolveStrategy::Stringify);
-                    let mut output = Cursor::new(Vec::new());
+                    let mut tracker = MeltTracker::new(&mut output, options);
+                    let options = options.ck3();
+                    log::debug(format_args!("reading the metadata entry from the ck3 zip"));
                     let mut meta = zip.meta()?;
                     let doc = ck3save::Ck3Melt::melt(
                         &mut meta,
                         options,
-                        ck3_tokens_resolver(),
-                        &mut output,
-                    )?;
+                        tracker.resolver(ck3_tokens_resolver()?),
+                        &mut tracker,
+                    );
+                    let doc = tracker.with_key_path(doc)?;
                     if file.header().kind().is_text() {
-                        Ok(MeltedBuffer::Text {
-                            header: Vec::new(),
-                            body: output.into_inner(),
-                        })
+                        Ok(MeltOutcome::Text)
                     } else {
-                        Ok(MeltedBuffer::Binary {
-                            body: output.into_inner(),
-                            unknown_tokens: !doc.unknown_tokens().is_empty(),
+                        Ok(MeltOutcome::Binary {
+                            unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
                         })
                     }
                 }
             },
-            PdsMeta::Imperator(file) => match file.kind() {
-                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => Ok(MeltedBuffer::Verbatim),
+            SaveMeta::Imperator(file) => match file.kind() {
+                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => Ok(MeltOutcome::Verbatim),
                 JominiFileKind::Uncompressed(SaveDataKind::Binary(binary)) => {
-                    let options = imperator_save::MeltOptions::new()
-                        .verbatim(true)
-                        .on_failed_resolve(imperator_save::FailedResolveStrategy::Stringify);
-                    let mut output = Cursor::new(Vec::new());
+                    let mut tracker = MeltTracker::new(&mut output, options);
+                    let options = options.imperator();
                     let doc = imperator_save::ImperatorMelt::melt(
                         &mut &*binary,
                         options,
-                        imperator_tokens_resolver(),
-                        &mut output,
-                    )?;
-                    Ok(MeltedBuffer::Binary {
-                        body: output.into_inner(),
-                        unknown_tokens: !doc.unknown_tokens().is_empty(),
+                        tracker.resolver(imperator_tokens_resolver()?),
+                        &mut tracker,
+                    );
+                    let doc = tracker.with_key_path(doc)?;
+                    Ok(MeltOutcome::Binary {
+                        unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
                     })
                 }
                 JominiFileKind::Zip(zip) => {
-                    let options = imperator_save::MeltOptions::new()
-                        .verbatim(true)
-                        .on_failed_resolve(imperator_save::FailedResolveStrategy::Stringify);
-                    let mut output = Cursor::new(Vec::new());
+                    let mut tracker = MeltTracker::new(&mut output, options);
+                    let options = options.imperator();
+                    log::debug(format_args!(
+                        "reading the metadata entry from the imperator zip"
+                    ));
                     let mut meta = zip.meta()?;
                     let doc = imperator_save::ImperatorMelt::melt(
                         &mut meta,
                         options,
-                        imperator_tokens_resolver(),
-                        &mut output,
-                    )?;
+                        tracker.resolver(imperator_tokens_resolver()?),
+                        &mut tracker,
+                    );
+                    let doc = tracker.with_key_path(doc)?;
                     if file.header().kind().is_text() {
-                        Ok(MeltedBuffer::Text {
-                            header: Vec::new(),
-                            body: output.into_inner(),
-                        })
+                        Ok(MeltOutcome::Text)
                     } else {
-                        Ok(MeltedBuffer::Binary {
-                            body: output.into_inner(),
-                            unknown_tokens: !doc.unknown_tokens().is_empty(),
+                        Ok(MeltOutcome::Binary {
+                            unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
                         })
                     }
                 }
             },
 
-            PdsMeta::Vic3(file) => match file.kind() {
-                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => Ok(MeltedBuffer::Verbatim),
+            SaveMeta::Vic3(file) => match file.kind() {
+                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => Ok(MeltOutcome::Verbatim),
                 JominiFileKind::Uncompressed(SaveDataKind::Binary(binary)) => {
-                    let options = vic3save::MeltOptions::new()
-                        .verbatim(true)
-                        .on_failed_resolve(vic3save::FailedResolveStrategy::Stringify);
-                    let mut output = Cursor::new(Vec::new());
+                    let mut tracker = MeltTracker::new(&mut output, options);
+                    let options = options.vic3();
                     let doc = vic3save::Vic3Melt::melt(
                         &mut &*binary,
                         options,
-                        vic3_tokens_resolver(),
-                        &mut output,
-                    )?;
-                    Ok(MeltedBuffer::Binary {
-                        body: output.into_inner(),
-                        unknown_tokens: !doc.unknown_tokens().is_empty(),
+                        tracker.resolver(vic3_tokens_resolver()?),
+                        &mut tracker,
+                    );
+                    let doc = tracker.with_key_path(doc)?;
+                    Ok(MeltOutcome::Binary {
+                        unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
                     })
                 }
                 JominiFileKind::Zip(zip) => {
-                    let options = vic3save::MeltOptions::new()
-                        .verbatim(true)
-                        .on_failed_resolve(vic3save::FailedResolveStrategy::Stringify);
-                    let mut output = Cursor::new(Vec::new());
+                    let mut tracker = MeltTracker::new(&mut output, options);
+                    let options = options.vic3();
+                    log::debug(format_args!("reading the metadata entry from the vic3 zip"));
                     let mut meta = zip.meta()?;
                     let doc = vic3save::Vic3Melt::melt(
                         &mut meta,
                         options,
-                        vic3_tokens_resolver(),
-                        &mut output,
-                    )?;
+                        tracker.resolver(vic3_tokens_resolver()?),
+                        &mut tracker,
+                    );
+                    let doc = tracker.with_key_path(doc)?;
                     if file.header().kind().is_text() {
-                        Ok(MeltedBuffer::Text {
-                            header: Vec::new(),
-                            body: output.into_inner(),
-                        })
+                        Ok(MeltOutcome::Text)
                     } else {
-                        Ok(MeltedBuffer::Binary {
-                            body: output.into_inner(),
-                            unknown_tokens: !doc.unknown_tokens().is_empty(),
+                        Ok(MeltOutcome::Binary {
+                            unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
                         })
                     }
                 }
             },
-            PdsMeta::Eu5(file) => match file.kind() {
-                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => Ok(MeltedBuffer::Verbatim),
+            SaveMeta::Eu4Header(data) => {
+                let file = eu4save::Eu4File::from_slice(data)?;
+                match Melter::melt(&file, options, &mut output)? {
+                    MeltOutcome::Verbatim => {
+                        output.write_all(data)?;
+                        Ok(MeltOutcome::Text)
+                    }
+                    outcome => Ok(outcome),
+                }
+            }
+            SaveMeta::Hoi4Header(data) => {
+                let file = hoi4save::Hoi4File::from_slice(data)?;
+                match Melter::melt(&file, options, &mut output)? {
+                    MeltOutcome::Verbatim => {
+                        output.write_all(data)?;
+                        Ok(MeltOutcome::Text)
+                    }
+                    outcome => Ok(outcome),
+                }
+            }
+            SaveMeta::Eu5(file) => match file.kind() {
+                JominiFileKind::Uncompressed(SaveDataKind::Text(_)) => Ok(MeltOutcome::Verbatim),
                 JominiFileKind::Uncompressed(SaveDataKind::Binary(_)) => Err(
                     LibError::UnsupportedOperation(String::from("melting uncompressed eu5 binary")),
                 ),
                 JominiFileKind::Zip(zip) => {
-                    let options = eu5save::MeltOptions::new()
-                        .verbatim(true)
-                        .on_failed_resolve(eu5save::FailedResolveStrategy::Stringify);
-                    let mut output = Cursor::new(Vec::new());
+                    let mut tracker = MeltTracker::new(&mut output, options);
+                    let options = options.eu5();
+                    log::debug(format_args!("reading the metadata entry from the eu5 zip"));
                     let mut meta = zip.meta()?;
-                    let resolver = eu5save::SaveResolver::create(zip, eu5_tokens_resolver())?;
-                    let doc = eu5save::Eu5Melt::melt(&mut meta, options, resolver, &mut output)?;
+                    let resolver = eu5save::SaveResolver::create(
+                        zip,
+                        tracker.resolver(eu5_tokens_resolver()?),
+                    )?;
+                    let doc = eu5save::Eu5Melt::melt(&mut meta, options, resolver, &mut tracker);
+                    let doc = tracker.with_key_path(doc)?;
                     if file.header().kind().is_text() {
-                        Ok(MeltedBuffer::Text {
-                            header: Vec::new(),
-                            body: output.into_inner(),
-                        })
+                        Ok(MeltOutcome::Text)
                     } else {
-                        Ok(MeltedBuffer::Binary {
-                            body: output.into_inner(),
-                            unknown_tokens: !doc.unknown_tokens().is_empty(),
+                        Ok(MeltOutcome::Binary {
+                            unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
                         })
                     }
                 }
@@ -407,3 +722,382 @@ impl PdsMeta<'_> {
         }
     }
 }
+
+/// Returns the header line and metadata section of an uncompressed save that
+/// uses the jomini envelope
+fn jomini_meta<'a>(
+    data: &'a [u8],
+    file: &jomini::envelope::JominiFile<Cursor<&[u8]>>,
+) -> Option<&'a [u8]> {
+    match file.kind() {
+        JominiFileKind::Uncompressed(_) => {
+            let header = file.header();
+            let len = usize::try_from(header.metadata_len()).ok()?;
+            data.get(..header.header_len().checked_add(len)?)
+        }
+        JominiFileKind::Zip(_) => None,
+    }
+}
+
+/// Attaches the key path at the end of the output of a failed melt, unless
+/// the melt followed its output and already knows the key path
+fn with_output_key_path(err: LibError, output: &[u8]) -> LibError {
+    if err.key_path().is_some() {
+        err
+    } else {
+        err.with_key_path(tracker::key_path(output))
+    }
+}
+
+/// The fields found in the metadata entry of compressed EU4 saves
+const EU4_META_FIELDS: &[&str] = &[
+    "date",
+    "save_game",
+    "player",
+    "displayed_country_name",
+    "savegame_version",
+    "savegame_versions",
+    "dlc_enabled",
+    "multi_player",
+    "not_observer",
+    "campaign_id",
+    "campaign_length",
+    "campaign_stats",
+    "is_random_new_world",
+    "ironman",
+];
+
+/// EU4 header fields are expected among this many leading top level fields
+const EU4_HEADER_EXTENT: usize = 32;
+
+/// The fields that describe a HOI4 save
+const HOI4_META_FIELDS: &[&str] = &["player", "date", "version", "ironman"];
+
+/// HOI4 header fields are expected among this many leading top level fields
+const HOI4_HEADER_EXTENT: usize = 32;
+
+impl<'a> PdsFile<'a> {
+    /// Parses save data for a given game. When the game is unknown, it is
+    /// detected from the data.
+    pub(crate) fn from_slice(game: PdsGame, data: &'a [u8]) -> Result<Self, LibError> {
+        let save = match game {
+            PdsGame::Unknown => return Self::detect(data),
+            PdsGame::Eu4 => SaveFile::Eu4(eu4save::Eu4File::from_slice(data)?),
+            PdsGame::Ck3 => SaveFile::Ck3(ck3save::Ck3File::from_slice(data)?),
+            PdsGame::Imperator => {
+                SaveFile::Imperator(imperator_save::ImperatorFile::from_slice(data)?)
+            }
+            PdsGame::Hoi4 => SaveFile::Hoi4(hoi4save::Hoi4File::from_slice(data)?),
+            PdsGame::Vic3 => SaveFile::Vic3(vic3save::Vic3File::from_slice(data)?),
+            PdsGame::Eu5 => SaveFile::Eu5(eu5save::Eu5File::from_slice(data)?),
+        };
+
+        Ok(PdsFile::new(save, data))
+    }
+
+    fn new(save: SaveFile<'a>, data: &'a [u8]) -> Self {
+        PdsFile {
+            save,
+            data,
+            header: OnceLock::new(),
+            _owned: None,
+        }
+    }
+
+    /// Each game's parser is given a chance to validate the data's header.
+    /// As CK3, Imperator, Vic3, and EU5 all share the same save envelope, the
+    /// save's zip entries and metadata keys are inspected to break ties
+    /// between them. Saves that can't be told apart are not recognized.
+    fn detect(data: &'a [u8]) -> Result<Self, LibError> {
+        let mut attempts = Vec::new();
+
+        match eu4save::Eu4File::from_slice(data) {
+            Ok(x) => return Ok(PdsFile::new(SaveFile::Eu4(x), data)),
+            Err(e) => attempts.push((PdsGame::Eu4, LibError::from(e))),
+        }
+
+        match hoi4save::Hoi4File::from_slice(data) {
+            Ok(x) => return Ok(PdsFile::new(SaveFile::Hoi4(x), data)),
+            Err(e) => attempts.push((PdsGame::Hoi4, LibError::from(e))),
+        }
+
+        let mut candidates = Vec::new();
+        match ck3save::Ck3File::from_slice(data) {
+            Ok(x) => candidates.push(PdsFile::new(SaveFile::Ck3(x), data)),
+            Err(e) => attempts.push((PdsGame::Ck3, LibError::from(e))),
+        }
+
+        match vic3save::Vic3File::from_slice(data) {
+            Ok(x) => candidates.push(PdsFile::new(SaveFile::Vic3(x), data)),
+            Err(e) => attempts.push((PdsGame::Vic3, LibError::from(e))),
+        }
+
+        match eu5save::Eu5File::from_slice(data) {
+            Ok(x) => candidates.push(PdsFile::new(SaveFile::Eu5(x), data)),
+            Err(e) => attempts.push((PdsGame::Eu5, LibError::from(e))),
+        }
+
+        match imperator_save::ImperatorFile::from_slice(data) {
+            Ok(x) => candidates.push(PdsFile::new(SaveFile::Imperator(x), data)),
+            Err(e) => attempts.push((PdsGame::Imperator, LibError::from(e))),
+        }
+
+        for (game, err) in &attempts {
+            log::debug(format_args!("{} parser rejected file: {}", game, err));
+        }
+
+        if candidates.len() <= 1 {
+            return candidates
+                .pop()
+                .ok_or_else(|| LibError::UnrecognizedFile(attempts.into()));
+        }
+
+        let games = candidates.iter().map(PdsFile::game).collect::<Vec<_>>();
+        let evidence = match candidates[0].jomini_metadata() {
+            Some((metadata, binary)) => Evidence::new(data, metadata, binary),
+            None => Evidence::new(data, &[], false),
+        };
+
+        match detect::distinguish(&games, &evidence)? {
+            Some(game) => {
+                log::debug(format_args!(
+                    "detected {} save from its envelope among {} candidates",
+                    game,
+                    games.len()
+                ));
+                candidates
+                    .into_iter()
+                    .find(|x| x.game() == game)
+                    .ok_or(LibError::Indistinguishable)
+            }
+            None => {
+                attempts.extend(
+                    games
+                        .into_iter()
+                        .map(|game| (game, LibError::Indistinguishable)),
+                );
+                Err(LibError::UnrecognizedFile(attempts.into()))
+            }
+        }
+    }
+
+    /// Returns the metadata section that follows the header line of saves in
+    /// the jomini envelope and whether it is binary
+    fn jomini_metadata(&self) -> Option<(&'a [u8], bool)> {
+        let file = match &self.save {
+            SaveFile::Ck3(file)
+            | SaveFile::Imperator(file)
+            | SaveFile::Vic3(file)
+            | SaveFile::Eu5(file) => file,
+            SaveFile::Eu4(_) | SaveFile::Hoi4(_) => return None,
+        };
+
+        let header = file.header();
+        let start = self.data.iter().position(|&x| x == b'\n')? + 1;
+        let len = usize::try_from(header.metadata_len()).ok()?;
+        let metadata = self.data.get(start..start.checked_add(len)?)?;
+        Some((metadata, header.kind().is_binary()))
+    }
+}
+
+impl PdsFile<'static> {
+    /// Parses save data that the file takes ownership of, so that the file
+    /// does not borrow from the caller.
+    pub(crate) fn from_owned(game: PdsGame, data: Vec<u8>) -> Result<Self, LibError> {
+        let owned = OwnedData::Heap(Box::into_raw(data.into_boxed_slice()));
+        Self::from_owned_data(game, owned)
+    }
+
+    /// Parses the save at the given path by memory mapping it, so that large
+    /// saves do not need to be read into memory up front.
+    ///
+    /// The file must not be modified while the returned save is alive.
+    pub(crate) fn from_path(game: PdsGame, path: &Path) -> Result<Self, LibError> {
+        let file = std::fs::File::open(path)?;
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        log::debug(format_args!(
+            "memory mapped {} ({} bytes)",
+            path.display(),
+            mmap.len()
+        ));
+        Self::from_owned_data(game, OwnedData::Mapped(mmap))
+    }
+
+    fn from_owned_data(game: PdsGame, owned: OwnedData) -> Result<Self, LibError> {
+        // SAFETY: the owned data is stored alongside the save and is dropped
+        // after it
+        let data = unsafe { owned.as_static_slice() };
+        let file = PdsFile::from_slice(game, data)?;
+        Ok(PdsFile {
+            _owned: Some(owned),
+            ..file
+        })
+    }
+}
+
+/// Save data that is owned by a `PdsFile`.
+pub(crate) enum OwnedData {
+    /// Held as a raw pointer instead of a `Box` so that moving the data into
+    /// the `PdsFile` does not invalidate the save's borrows.
+    Heap(*mut [u8]),
+    Mapped(memmap2::Mmap),
+}
+
+// The data is never mutated and only freed on drop
+unsafe impl Send for OwnedData {}
+unsafe impl Sync for OwnedData {}
+
+impl OwnedData {
+    /// # Safety
+    ///
+    /// The returned slice must not be used after `self` is dropped
+    unsafe fn as_static_slice(&self) -> &'static [u8] {
+        match self {
+            OwnedData::Heap(ptr) => &**ptr,
+            OwnedData::Mapped(mmap) => std::slice::from_raw_parts(mmap.as_ptr(), mmap.len()),
+        }
+    }
+}
+
+impl Drop for OwnedData {
+    fn drop(&mut self) {
+        if let OwnedData::Heap(ptr) = *self {
+            drop(unsafe { Box::from_raw(ptr) });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils, tokens::embedded_tokens};
+
+    #[test]
+    fn detects_the_game_of_saves() {
+        for &(name, game) in test_utils::SAVES {
+            let Some(data) = test_utils::save(name) else {
+                continue;
+            };
+
+            // Binary saves that share an envelope are told apart by tokens
+            let shared = !matches!(game, PdsGame::Eu4 | PdsGame::Hoi4);
+            if shared && embedded_tokens(game).empty {
+                continue;
+            }
+
+            let file = PdsFile::from_slice(PdsGame::Unknown, &data).unwrap();
+            assert_eq!(file.game(), game, "{}", name);
+        }
+    }
+
+    #[test]
+    fn detects_plaintext_saves_without_tokens() {
+        for &(game, metadata) in test_utils::PLAINTEXT_METADATA {
+            let data = test_utils::plaintext_save(metadata);
+            let file = PdsFile::from_slice(PdsGame::Unknown, &data).unwrap();
+            assert_eq!(file.game(), game);
+        }
+    }
+
+    #[test]
+    fn games_from_c_are_validated() {
+        assert_eq!(
+            PdsGame::try_from(PdsGame::Hoi4 as u32).unwrap(),
+            PdsGame::Hoi4
+        );
+        let err = PdsGame::try_from(7).err().unwrap();
+        assert_eq!(err.code(), crate::errors::PdsErrorCode::InvalidArgument);
+    }
+
+    #[test]
+    fn unrecognized_data_lists_each_parser() {
+        let err = PdsFile::from_slice(PdsGame::Unknown, b"not a save")
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), crate::errors::PdsErrorCode::UnrecognizedFile);
+
+        let msg = err.to_string();
+        for game in ["eu4", "ck3", "imperator", "hoi4", "vic3", "eu5"] {
+            assert!(msg.contains(&format!("{} parser", game)), "{}", msg);
+        }
+    }
+
+    fn eu4_header(data: &[u8]) -> Vec<u8> {
+        let file = PdsFile::from_slice(PdsGame::Eu4, data).unwrap();
+        let meta = file.meta().unwrap().unwrap();
+        match meta.meta {
+            SaveMeta::Eu4Header(header) => header.to_vec(),
+            _ => panic!("expected an eu4 header"),
+        }
+    }
+
+    #[test]
+    fn eu4_text_header_skips_unwanted_fields() {
+        let data = b"EU4txt\ndate=1444.11.11\nplayer=\"FRA\"\nprovinces={ -1={ } }\nironman=yes";
+        assert_eq!(
+            eu4_header(data),
+            b"EU4txt\ndate=1444.11.11\nplayer=\"FRA\"\nironman=yes\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn eu4_binary_header_skips_unwanted_fields() {
+        const EQUAL: [u8; 2] = [0x01, 0x00];
+        const OPEN: [u8; 2] = [0x03, 0x00];
+        const CLOSE: [u8; 2] = [0x04, 0x00];
+        const QUOTED: [u8; 2] = [0x0f, 0x00];
+        const BOOL: [u8; 2] = [0x0e, 0x00];
+        const I32: [u8; 2] = [0x0c, 0x00];
+
+        let mut fields = Vec::new();
+        fields.extend_from_slice(&0xfff0u16.to_le_bytes());
+        fields.extend_from_slice(&EQUAL);
+        fields.extend_from_slice(&I32);
+        fields.extend_from_slice(&56379360i32.to_le_bytes());
+        fields.extend_from_slice(&0xfff1u16.to_le_bytes());
+        fields.extend_from_slice(&EQUAL);
+        fields.extend_from_slice(&QUOTED);
+        fields.extend_from_slice(&3u16.to_le_bytes());
+        fields.extend_from_slice(b"FRA");
+
+        let mut ironman = Vec::new();
+        ironman.extend_from_slice(&0xfff3u16.to_le_bytes());
+        ironman.extend_from_slice(&EQUAL);
+        ironman.extend_from_slice(&BOOL);
+        ironman.push(1);
+
+        let mut data = b"EU4bin".to_vec();
+        data.extend_from_slice(&fields);
+        data.extend_from_slice(&0xfff2u16.to_le_bytes());
+        data.extend_from_slice(&EQUAL);
+        data.extend_from_slice(&OPEN);
+        data.extend_from_slice(&CLOSE);
+        data.extend_from_slice(&ironman);
+
+        let names = b"0xfff0 date\n0xfff1 player\n0xfff2 provinces\n0xfff3 ironman";
+        let _tokens = test_utils::load_tokens(PdsGame::Eu4, names);
+        assert_eq!(
+            eu4_header(&data),
+            [b"EU4bin".as_slice(), &fields, &ironman].concat()
+        );
+    }
+
+    #[test]
+    fn eu4_header_of_unusual_saves() {
+        // Windows line endings and padded operators
+        let data = b"EU4txt\r\n  date = 1444.11.11\r\n\tplayer =\"FRA\"\r\nprovinces={ }";
+        assert_eq!(
+            eu4_header(data),
+            b"EU4txt\ndate = 1444.11.11\nplayer =\"FRA\"\n".to_vec()
+        );
+
+        // A save without any metadata fields
+        let data = b"EU4txt\nprovinces={ }\nspeed=2";
+        assert_eq!(eu4_header(data), b"EU4txt\n".to_vec());
+
+        // The header is synthesized once
+        let file = PdsFile::from_slice(PdsGame::Eu4, b"EU4txt\ndate=1444.11.11").unwrap();
+        let first = file.header().unwrap().as_ptr();
+        assert_eq!(file.header().unwrap().as_ptr(), first);
+    }
+}
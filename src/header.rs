@@ -0,0 +1,304 @@
+//! Scanners that collect the fields of a save's header from its leading top
+//! level fields, so that a save's metadata can be extracted without parsing
+//! the entire save.
+
+use crate::tokens::TokenTable;
+
+/// Tracks which of the wanted fields were seen
+struct Wanted<'a> {
+    names: &'a [&'a str],
+    found: Vec<bool>,
+}
+
+impl<'a> Wanted<'a> {
+    fn new(names: &'a [&'a str]) -> Self {
+        Wanted {
+            names,
+            found: vec![false; names.len()],
+        }
+    }
+
+    /// Records that the field at the index was seen, returning true if it is
+    /// wanted
+    fn mark(&mut self, index: Option<usize>) -> bool {
+        match index {
+            Some(i) => {
+                self.found[i] = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn text(&mut self, key: &[u8]) -> bool {
+        let index = self.names.iter().position(|x| x.as_bytes() == key);
+        self.mark(index)
+    }
+
+    fn is_complete(&self) -> bool {
+        self.found.iter().all(|&x| x)
+    }
+
+    /// Returns if the scan should continue after the number of fields that
+    /// were scanned
+    fn next(&self, budget: usize, scanned: usize) -> bool {
+        scanned < budget && !self.is_complete()
+    }
+}
+
+/// Returns the wanted top level fields among the given number of leading top
+/// level fields of binary data, in the order they appear. The scan ends early
+/// once every wanted field is found. The keys of binary fields are usually
+/// tokens, which are looked up in the given table.
+pub fn binary_header(data: &[u8], names: &[&str], tokens: &TokenTable, budget: usize) -> Vec<u8> {
+    use jomini::binary::{Token, TokenReader};
+
+    let ids: Vec<Option<u16>> = names.iter().map(|x| tokens.id(x)).collect();
+    let mut wanted = Wanted::new(names);
+    let mut reader = TokenReader::from_slice(data);
+    let mut fields = Vec::new();
+
+    // A field whose value is a token may be followed by an object that the
+    // token tags, like `color=rgb { 1 2 3 }`
+    let mut tagging: Option<bool> = None;
+    let mut scanned = 0;
+
+    loop {
+        let start = reader.position();
+        let keep = match reader.next() {
+            Ok(Some(Token::Open)) if tagging.is_some() => {
+                if reader.skip_container().is_err() {
+                    break;
+                }
+
+                if tagging.take() == Some(true) {
+                    fields.extend_from_slice(&data[start..reader.position()]);
+                }
+                continue;
+            }
+            Ok(Some(Token::Id(id))) => {
+                let index = ids.iter().position(|&x| x == Some(id));
+                wanted.mark(index)
+            }
+            Ok(Some(Token::Quoted(key))) | Ok(Some(Token::Unquoted(key))) => {
+                wanted.text(key.as_bytes())
+            }
+            _ => break,
+        };
+
+        if !matches!(reader.read(), Ok(Token::Equal)) {
+            break;
+        }
+
+        tagging = None;
+        let complete = match reader.read() {
+            Ok(Token::Open) => reader.skip_container().is_ok(),
+            Ok(Token::Id(_)) => {
+                tagging = Some(keep);
+                true
+            }
+            Ok(Token::Close) | Ok(Token::Equal) | Err(_) => false,
+            Ok(_) => true,
+        };
+
+        if !complete {
+            break;
+        }
+
+        if keep {
+            fields.extend_from_slice(&data[start..reader.position()]);
+        }
+
+        scanned += 1;
+        if !wanted.next(budget, scanned) {
+            break;
+        }
+    }
+
+    fields
+}
+
+/// Returns the wanted top level fields among the given number of leading top
+/// level fields of plaintext data, in the order they appear, each on its own
+/// line. The scan ends early once every wanted field is found.
+pub fn text_header(data: &[u8], names: &[&str], budget: usize) -> Vec<u8> {
+    let mut wanted = Wanted::new(names);
+    let mut fields = Vec::new();
+    let mut pos = skip_whitespace(data, 0);
+    let mut scanned = 0;
+
+    loop {
+        let start = pos;
+        let key_end = scalar_end(data, pos);
+        if key_end == pos {
+            break;
+        }
+
+        let keep = wanted.text(&data[pos..key_end]);
+        pos = skip_whitespace(data, key_end);
+        if data.get(pos) != Some(&b'=') {
+            break;
+        }
+
+        pos = skip_whitespace(data, pos + 1);
+        let end = match data.get(pos) {
+            Some(b'{') => container_end(data, pos + 1),
+            Some(b'"') => quote_end(data, pos + 1),
+            Some(_) => {
+                let end = scalar_end(data, pos);
+                let next = skip_whitespace(data, end);
+
+                // A scalar may tag an object, like `color=rgb { 1 2 3 }`
+                if end == pos {
+                    None
+                } else if data.get(next) == Some(&b'{') {
+                    container_end(data, next + 1)
+                } else {
+                    Some(end)
+                }
+            }
+            None => None,
+        };
+
+        let Some(end) = end else {
+            break;
+        };
+
+        if keep {
+            fields.extend_from_slice(&data[start..end]);
+            fields.push(b'\n');
+        }
+
+        pos = skip_whitespace(data, end);
+        scanned += 1;
+        if !wanted.next(budget, scanned) {
+            break;
+        }
+    }
+
+    fields
+}
+
+fn skip_whitespace(data: &[u8], pos: usize) -> usize {
+    data[pos.min(data.len())..]
+        .iter()
+        .position(|x| !x.is_ascii_whitespace())
+        .map_or(data.len(), |x| pos + x)
+}
+
+fn scalar_end(data: &[u8], pos: usize) -> usize {
+    data[pos.min(data.len())..]
+        .iter()
+        .position(|x| x.is_ascii_whitespace() || matches!(x, b'=' | b'{' | b'}' | b'"'))
+        .map_or(data.len(), |x| pos + x)
+}
+
+/// Returns the position after the closing quote
+fn quote_end(data: &[u8], mut pos: usize) -> Option<usize> {
+    while let Some(&x) = data.get(pos) {
+        match x {
+            b'\\' => pos += 2,
+            b'"' => return Some(pos + 1),
+            _ => pos += 1,
+        }
+    }
+    None
+}
+
+/// Returns the position after the closing brace
+fn container_end(data: &[u8], mut pos: usize) -> Option<usize> {
+    let mut depth = 1;
+    while let Some(&x) = data.get(pos) {
+        match x {
+            b'"' => {
+                pos = quote_end(data, pos + 1)?;
+                continue;
+            }
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(pos + 1);
+                }
+            }
+            _ => {}
+        }
+        pos += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOI4_FIELDS: &[&str] = &["player", "date", "version", "ironman"];
+
+    #[test]
+    fn collects_plaintext_fields_past_objects() {
+        let data = b"player=\"GER\"\nideology=fascism\ncountries={ GER={ a=b } }\n\
+            color=rgb { 1 2 3 }\ndate=\"1936.1.1.12\"\nversion=\"v1.10\"\nironman=yes\n\
+            states={ 1={} }\n";
+        let fields = text_header(data, HOI4_FIELDS, 16);
+        assert_eq!(
+            std::str::from_utf8(&fields).unwrap(),
+            "player=\"GER\"\ndate=\"1936.1.1.12\"\nversion=\"v1.10\"\nironman=yes\n"
+        );
+    }
+
+    #[test]
+    fn plaintext_scan_stops_at_the_budget() {
+        let data = b"player=\"GER\"\ncountries={ }\nstates={ }\ndate=\"1936.1.1.12\"";
+        let fields = text_header(data, HOI4_FIELDS, 2);
+        assert_eq!(std::str::from_utf8(&fields).unwrap(), "player=\"GER\"\n");
+    }
+
+    #[test]
+    fn wanted_fields_after_unwanted_fields_are_collected() {
+        let data = b"date=1444.11.11\nplayer=\"FRA\"\nprovinces={ -1={ } }\nironman=yes";
+        let fields = text_header(data, &["date", "player", "ironman"], 16);
+        assert_eq!(
+            std::str::from_utf8(&fields).unwrap(),
+            "date=1444.11.11\nplayer=\"FRA\"\nironman=yes\n"
+        );
+    }
+
+    #[test]
+    fn collects_binary_fields_past_objects() {
+        const EQUAL: [u8; 2] = [0x01, 0x00];
+        const OPEN: [u8; 2] = [0x03, 0x00];
+        const CLOSE: [u8; 2] = [0x04, 0x00];
+        const QUOTED: [u8; 2] = [0x0f, 0x00];
+        const BOOL: [u8; 2] = [0x0e, 0x00];
+        const I32: [u8; 2] = [0x0c, 0x00];
+
+        let tokens =
+            TokenTable::parse(b"0x2000 player\n0x2001 countries\n0x2002 version\n0x2003 ironman")
+                .unwrap();
+
+        let mut player = Vec::new();
+        player.extend_from_slice(&0x2000u16.to_le_bytes());
+        player.extend_from_slice(&EQUAL);
+        player.extend_from_slice(&QUOTED);
+        player.extend_from_slice(&3u16.to_le_bytes());
+        player.extend_from_slice(b"GER");
+
+        let mut countries = Vec::new();
+        countries.extend_from_slice(&0x2001u16.to_le_bytes());
+        countries.extend_from_slice(&EQUAL);
+        countries.extend_from_slice(&OPEN);
+        countries.extend_from_slice(&I32);
+        countries.extend_from_slice(&1i32.to_le_bytes());
+        countries.extend_from_slice(&CLOSE);
+
+        let mut ironman = Vec::new();
+        ironman.extend_from_slice(&0x2003u16.to_le_bytes());
+        ironman.extend_from_slice(&EQUAL);
+        ironman.extend_from_slice(&BOOL);
+        ironman.push(1);
+
+        let data = [player.clone(), countries, ironman.clone()].concat();
+        let fields = binary_header(&data, HOI4_FIELDS, &tokens, 16);
+        assert_eq!(fields, [player, ironman].concat());
+    }
+}
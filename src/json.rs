@@ -0,0 +1,141 @@
+use crate::{errors::LibError, file::PdsGame};
+use jomini::{
+    json::{DuplicateKeyMode, JsonOptions},
+    TextTape,
+};
+use std::convert::TryFrom;
+
+/// How keys that appear multiple times in an object are written to JSON
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdsJsonDuplicateKeys {
+    /// Duplicate keys are written as is, which is valid JSON but may be
+    /// collapsed by JSON parsers that keep only the last value
+    Preserve = 0,
+
+    /// Values of duplicate keys are grouped into an array under a single key
+    Group = 1,
+
+    /// Objects are written as arrays of key value pairs
+    KeyValuePairs = 2,
+}
+
+impl TryFrom<u32> for PdsJsonDuplicateKeys {
+    type Error = LibError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(PdsJsonDuplicateKeys::Preserve),
+            1 => Ok(PdsJsonDuplicateKeys::Group),
+            2 => Ok(PdsJsonDuplicateKeys::KeyValuePairs),
+            _ => Err(LibError::invalid_enum("PdsJsonDuplicateKeys", value)),
+        }
+    }
+}
+
+/// Converts melted plaintext of a game into JSON
+pub fn to_json(
+    game: PdsGame,
+    data: &[u8],
+    pretty: bool,
+    duplicate_keys: PdsJsonDuplicateKeys,
+) -> Result<Vec<u8>, LibError> {
+    let mode = match duplicate_keys {
+        PdsJsonDuplicateKeys::Preserve => DuplicateKeyMode::Preserve,
+        PdsJsonDuplicateKeys::Group => DuplicateKeyMode::Group,
+        PdsJsonDuplicateKeys::KeyValuePairs => DuplicateKeyMode::KeyValuePairs,
+    };
+
+    let options = JsonOptions::new()
+        .with_prettyprint(pretty)
+        .with_duplicate_keys(mode);
+
+    let tape = TextTape::from_slice(strip_header(data))?;
+    let mut output = Vec::new();
+    if game == PdsGame::Eu4 {
+        let reader = tape.windows1252_reader();
+        reader.json().with_options(options).to_writer(&mut output)?;
+    } else {
+        let reader = tape.utf8_reader();
+        reader.json().with_options(options).to_writer(&mut output)?;
+    }
+
+    Ok(output)
+}
+
+/// Removes the line that identifies the game and format of plaintext saves
+pub(crate) fn strip_header(data: &[u8]) -> &[u8] {
+    if let Some(rest) = data.strip_prefix(b"EU4txt") {
+        rest
+    } else if let Some(rest) = data.strip_prefix(b"HOI4txt") {
+        rest
+    } else if data.starts_with(b"SAV") {
+        let end = data
+            .iter()
+            .position(|&x| x == b'\n')
+            .map_or(data.len(), |x| x + 1);
+        &data[end..]
+    } else {
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(game: PdsGame, data: &[u8], keys: PdsJsonDuplicateKeys) -> String {
+        String::from_utf8(to_json(game, data, false, keys).unwrap()).unwrap()
+    }
+
+    const SAVE: &[u8] = b"EU4txt\ncore=FRA\ncore=ENG\nflags={ a=yes b=2 }\n";
+
+    #[test]
+    fn preserved_duplicate_keys_are_written_as_is() {
+        let json = json(PdsGame::Eu4, SAVE, PdsJsonDuplicateKeys::Preserve);
+        assert_eq!(
+            json,
+            r#"{"core":"FRA","core":"ENG","flags":{"a":true,"b":2}}"#
+        );
+    }
+
+    #[test]
+    fn grouped_duplicate_keys_are_written_as_an_array() {
+        let json = json(PdsGame::Eu4, SAVE, PdsJsonDuplicateKeys::Group);
+        assert_eq!(json, r#"{"core":["FRA","ENG"],"flags":{"a":true,"b":2}}"#);
+    }
+
+    #[test]
+    fn objects_are_written_as_key_value_pairs() {
+        let json = json(PdsGame::Eu4, SAVE, PdsJsonDuplicateKeys::KeyValuePairs);
+        assert_eq!(
+            json,
+            r#"[["core","FRA"],["core","ENG"],["flags",[["a",true],["b",2]]]]"#
+        );
+    }
+
+    #[test]
+    fn strings_are_decoded_per_game() {
+        let eu4 = json(
+            PdsGame::Eu4,
+            b"name=\"Bj\xf6rn\"",
+            PdsJsonDuplicateKeys::Preserve,
+        );
+        assert_eq!(eu4, "{\"name\":\"Bj\u{f6}rn\"}");
+
+        let data = "SAV0103\nname=\"Bj\u{f6}rn\"".as_bytes();
+        let ck3 = json(PdsGame::Ck3, data, PdsJsonDuplicateKeys::Preserve);
+        assert_eq!(ck3, "{\"name\":\"Bj\u{f6}rn\"}");
+    }
+
+    #[test]
+    fn truncated_plaintext_is_an_error() {
+        for keys in [
+            PdsJsonDuplicateKeys::Preserve,
+            PdsJsonDuplicateKeys::Group,
+            PdsJsonDuplicateKeys::KeyValuePairs,
+        ] {
+            assert!(to_json(PdsGame::Ck3, b"a={ b={ 1", false, keys).is_err());
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! A lexer for plaintext that arrives in chunks, like melted output as it is
+//! written, so that the text can be inspected without buffering all of it.
+
+/// A token of plaintext
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lexeme<'a> {
+    Open,
+    Close,
+
+    /// An `=` or a comparison like `>=`
+    Operator(&'a [u8]),
+
+    Unquoted(&'a [u8]),
+
+    /// The contents of a quoted scalar with escapes left as is
+    Quoted(&'a [u8]),
+}
+
+/// Receives the tokens of the lexed plaintext
+pub trait LexemeSink {
+    fn lexeme(&mut self, lexeme: Lexeme);
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+enum State {
+    #[default]
+    Between,
+    Unquoted,
+    Quoted {
+        escaped: bool,
+    },
+    Operator,
+    Comment,
+}
+
+/// Splits plaintext into tokens as it is fed. A token that is cut off by the
+/// end of a chunk is held until the rest of it is fed.
+#[derive(Debug, Default)]
+pub struct Lexer {
+    state: State,
+    pending: Vec<u8>,
+}
+
+impl Lexer {
+    pub fn feed<S: LexemeSink>(&mut self, data: &[u8], sink: &mut S) {
+        let mut pos = 0;
+        while let Some(&x) = data.get(pos) {
+            match self.state {
+                State::Between => {
+                    pos += 1;
+                    match x {
+                        b'{' => sink.lexeme(Lexeme::Open),
+                        b'}' => sink.lexeme(Lexeme::Close),
+                        b'"' => self.state = State::Quoted { escaped: false },
+                        b'#' => self.state = State::Comment,
+                        x if x.is_ascii_whitespace() || x == b';' => {}
+                        x if is_operator(x) => {
+                            self.state = State::Operator;
+                            pos -= 1;
+                        }
+                        _ => {
+                            self.state = State::Unquoted;
+                            pos -= 1;
+                        }
+                    }
+                }
+                State::Unquoted => {
+                    let rest = &data[pos..];
+                    match rest.iter().position(|&x| is_boundary(x)) {
+                        Some(end) => {
+                            let scalar = self.take(&rest[..end]);
+                            sink.lexeme(Lexeme::Unquoted(scalar));
+                            self.pending.clear();
+                            self.state = State::Between;
+                            pos += end;
+                        }
+                        None => {
+                            self.pending.extend_from_slice(rest);
+                            pos = data.len();
+                        }
+                    }
+                }
+                State::Operator => {
+                    let rest = &data[pos..];
+                    match rest.iter().position(|&x| !is_operator(x)) {
+                        Some(end) => {
+                            let operator = self.take(&rest[..end]);
+                            sink.lexeme(Lexeme::Operator(operator));
+                            self.pending.clear();
+                            self.state = State::Between;
+                            pos += end;
+                        }
+                        None => {
+                            self.pending.extend_from_slice(rest);
+                            pos = data.len();
+                        }
+                    }
+                }
+                State::Quoted { mut escaped } => {
+                    let rest = &data[pos..];
+                    let end = rest.iter().position(|&x| {
+                        let closes = x == b'"' && !escaped;
+                        escaped = x == b'\\' && !escaped;
+                        closes
+                    });
+
+                    match end {
+                        Some(end) => {
+                            let scalar = self.take(&rest[..end]);
+                            sink.lexeme(Lexeme::Quoted(scalar));
+                            self.pending.clear();
+                            self.state = State::Between;
+                            pos += end + 1;
+                        }
+                        None => {
+                            self.pending.extend_from_slice(rest);
+                            self.state = State::Quoted { escaped };
+                            pos = data.len();
+                        }
+                    }
+                }
+                State::Comment => match data[pos..].iter().position(|&x| x == b'\n') {
+                    Some(end) => {
+                        self.state = State::Between;
+                        pos += end + 1;
+                    }
+                    None => pos = data.len(),
+                },
+            }
+        }
+    }
+
+    /// Emits the token that was cut off by the end of the plaintext, if any
+    pub fn finish<S: LexemeSink>(&mut self, sink: &mut S) {
+        match std::mem::take(&mut self.state) {
+            State::Unquoted => sink.lexeme(Lexeme::Unquoted(&self.pending)),
+            State::Operator => sink.lexeme(Lexeme::Operator(&self.pending)),
+            State::Quoted { .. } => sink.lexeme(Lexeme::Quoted(&self.pending)),
+            State::Between | State::Comment => {}
+        }
+        self.pending.clear();
+    }
+
+    /// Returns the rest of a token, joined with the part held from earlier
+    /// chunks when there is one
+    fn take<'a>(&'a mut self, rest: &'a [u8]) -> &'a [u8] {
+        if self.pending.is_empty() {
+            rest
+        } else {
+            self.pending.extend_from_slice(rest);
+            &self.pending
+        }
+    }
+}
+
+fn is_operator(x: u8) -> bool {
+    matches!(x, b'=' | b'<' | b'>')
+}
+
+fn is_boundary(x: u8) -> bool {
+    x.is_ascii_whitespace() || is_operator(x) || matches!(x, b'{' | b'}' | b'"' | b'#' | b';')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Collect(Vec<String>);
+
+    impl LexemeSink for Collect {
+        fn lexeme(&mut self, lexeme: Lexeme) {
+            let token = match lexeme {
+                Lexeme::Open => String::from("{"),
+                Lexeme::Close => String::from("}"),
+                Lexeme::Operator(x) => format!("op:{}", String::from_utf8_lossy(x)),
+                Lexeme::Unquoted(x) => format!("u:{}", String::from_utf8_lossy(x)),
+                Lexeme::Quoted(x) => format!("q:{}", String::from_utf8_lossy(x)),
+            };
+            self.0.push(token);
+        }
+    }
+
+    fn lex_chunks(data: &[u8], chunk: usize) -> Vec<String> {
+        let mut lexer = Lexer::default();
+        let mut sink = Collect::default();
+        for x in data.chunks(chunk) {
+            lexer.feed(x, &mut sink);
+        }
+        lexer.finish(&mut sink);
+        sink.0
+    }
+
+    #[test]
+    fn chunk_boundaries_do_not_split_tokens() {
+        let data =
+            b"EU4txt\ndate=1444.11.11 # comment\nname=\"a \\\"b\\\" c\"\nflags={ a>=1 b }\nid=12";
+        let expected = lex_chunks(data, data.len());
+        assert_eq!(
+            expected,
+            vec![
+                "u:EU4txt",
+                "u:date",
+                "op:=",
+                "u:1444.11.11",
+                "u:name",
+                "op:=",
+                "q:a \\\"b\\\" c",
+                "u:flags",
+                "op:=",
+                "{",
+                "u:a",
+                "op:>=",
+                "u:1",
+                "u:b",
+                "}",
+                "u:id",
+                "op:=",
+                "u:12",
+            ]
+        );
+
+        for chunk in 1..data.len() {
+            assert_eq!(lex_chunks(data, chunk), expected, "chunk size {}", chunk);
+        }
+    }
+}
@@ -1,14 +1,41 @@
+mod detect;
+mod diagnostics;
 mod errors;
 mod file;
+mod header;
+mod json;
+mod lexer;
+mod log;
 mod melter;
+mod options;
+mod summary;
+#[cfg(test)]
+mod test_utils;
 mod tokens;
+mod tracker;
+mod unwind;
 
 use crate::errors::LibError;
-use errors::PdsError;
-use file::{PdsFile, PdsFileResult, PdsMeta};
-use libc::{c_char, c_int, c_uchar, size_t};
-use melter::{MeltedBuffer, MeltedBufferResult};
-use std::hint::unreachable_unchecked;
+use errors::{record_last_error, take_last_error, PdsError, PdsErrorCode};
+use file::{PdsEncoding, PdsFile, PdsFileResult, PdsGame, PdsMeta};
+use jomini::binary::TokenResolver;
+use json::PdsJsonDuplicateKeys;
+use libc::{c_char, c_int, c_uchar, c_void, size_t};
+use log::{PdsLogLevel, RakalyLogCallback};
+use melter::{CallbackWriter, MeltOutcome, MeltedBuffer, MeltedBufferResult, PdsUnknownToken};
+use options::{MeltOptions, PdsFailedResolve};
+use std::{
+    convert::TryFrom,
+    ffi::{CStr, OsString},
+    fs::{File, OpenOptions},
+    hint::unreachable_unchecked,
+    io::BufWriter,
+    panic::UnwindSafe,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use summary::{PdsSummary, PdsSummaryField, PdsSummaryResult};
+use tokens::{PdsEmbeddedTokens, PdsTokensMode};
 
 /// Destroys a `MeltedBuffer` once you are done with it.
 ///
@@ -36,6 +63,92 @@ pub unsafe extern "C" fn rakaly_melt_data_length(res: *const MeltedBuffer) -> si
     (*res).len()
 }
 
+/// Returns a pointer to the melted data when it is stored in a single
+/// segment, so that it can be read without copying it out with
+/// `rakaly_melt_write_data`. The data is `rakaly_melt_data_length` bytes long
+/// and is not null terminated.
+///
+/// Null is returned when there is no melted data, as is the case for verbatim
+/// and streamed output, or when the data is split across segments, which can
+/// instead be read with `rakaly_melt_segment_ptr`.
+///
+/// The pointer is borrowed from the `MeltedBuffer` and is valid until the
+/// buffer is freed with `rakaly_free_melt`.
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to a `MeltedBuffer`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_melt_data_ptr(res: *const MeltedBuffer) -> *const c_char {
+    if res.is_null() {
+        return std::ptr::null();
+    }
+
+    let mut segments = (*res).segments();
+    match (segments.next(), segments.next()) {
+        (Some(data), None) => data.as_ptr() as *const c_char,
+        _ => std::ptr::null(),
+    }
+}
+
+/// Returns the number of segments that the melted data is stored in, which is
+/// at most two. Concatenating the segments in order yields the same data that
+/// `rakaly_melt_write_data` copies out.
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to a `MeltedBuffer`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_melt_segment_count(res: *const MeltedBuffer) -> size_t {
+    if res.is_null() {
+        return 0;
+    }
+
+    (*res).segments().count()
+}
+
+/// Returns a pointer to the segment of melted data at the given index, or null
+/// if the index is out of bounds. The segment is not null terminated.
+///
+/// The pointer is borrowed from the `MeltedBuffer` and is valid until the
+/// buffer is freed with `rakaly_free_melt`.
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to a `MeltedBuffer`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_melt_segment_ptr(
+    res: *const MeltedBuffer,
+    index: size_t,
+) -> *const c_char {
+    if res.is_null() {
+        return std::ptr::null();
+    }
+
+    (*res)
+        .segments()
+        .nth(index)
+        .map_or(std::ptr::null(), |x| x.as_ptr() as *const c_char)
+}
+
+/// Returns the length in bytes of the segment of melted data at the given
+/// index, or 0 if the index is out of bounds.
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to a `MeltedBuffer`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_melt_segment_length(
+    res: *const MeltedBuffer,
+    index: size_t,
+) -> size_t {
+    if res.is_null() {
+        return 0;
+    }
+
+    (*res).segments().nth(index).map_or(0, |x| x.len())
+}
+
 /// Returns true if the melter performed no work on the input
 ///
 /// # Safety
@@ -61,13 +174,130 @@ pub unsafe extern "C" fn rakaly_melt_binary_unknown_tokens(res: *const MeltedBuf
         return false;
     }
 
-    matches!(
-        &*res,
-        MeltedBuffer::Binary {
-            unknown_tokens: true,
-            ..
-        }
-    )
+    (*res).unknown_tokens().is_some_and(|x| !x.is_empty())
+}
+
+/// Returns the number of distinct unknown tokens the melter encountered in the
+/// binary input
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to a `MeltedBuffer`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_melt_unknown_tokens_count(res: *const MeltedBuffer) -> size_t {
+    if res.is_null() {
+        return 0;
+    }
+
+    (*res).unknown_tokens().map_or(0, |x| x.tokens().len())
+}
+
+/// Returns the unknown token at the given index, where the index is less than
+/// `rakaly_melt_unknown_tokens_count`. Unknown tokens are sorted by their id.
+///
+/// A zeroed token is returned when the index is out of bounds.
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to a `MeltedBuffer`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_melt_unknown_token(
+    res: *const MeltedBuffer,
+    index: size_t,
+) -> PdsUnknownToken {
+    if res.is_null() {
+        return PdsUnknownToken::default();
+    }
+
+    (*res)
+        .unknown_tokens()
+        .and_then(|x| x.tokens().get(index))
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Calculate the number of bytes in the key path where the unknown token at
+/// the given index was first encountered. Paths are only recorded when
+/// diagnostics are enabled in the melt options, so 0 is returned otherwise.
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to a `MeltedBuffer`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_melt_unknown_token_path_length(
+    res: *const MeltedBuffer,
+    index: size_t,
+) -> c_int {
+    if res.is_null() {
+        return 0;
+    }
+
+    (*res)
+        .unknown_tokens()
+        .and_then(|x| x.context(index))
+        .map_or(0, |x| x.path().len() as c_int)
+}
+
+/// Write the key path where the unknown token at the given index was first
+/// encountered into a caller-provided buffer as a UTF-8 string, returning the
+/// number of bytes written. Keys are separated by `/` and the path ends with
+/// the token when the token was a key.
+///
+/// `-1` is returned if there are any errors, for example when passed a
+/// null pointer, a negative length, or a buffer of insufficient size.
+///
+/// The buffer will not be null terminated.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `MeltedBuffer`
+/// - Given buffer must be at least the given length in size
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_melt_unknown_token_write_path(
+    res: *const MeltedBuffer,
+    index: size_t,
+    buffer: *mut c_char,
+    length: c_int,
+) -> c_int {
+    if res.is_null() || buffer.is_null() || length < 0 {
+        return -1;
+    }
+
+    let path = (*res)
+        .unknown_tokens()
+        .and_then(|x| x.context(index))
+        .map_or("", |x| x.path());
+    let buffer = std::slice::from_raw_parts_mut(buffer as *mut u8, length as usize);
+
+    if path.len() > buffer.len() {
+        return -1;
+    }
+
+    std::ptr::copy_nonoverlapping(path.as_ptr(), buffer.as_mut_ptr(), path.len());
+
+    path.len() as c_int
+}
+
+/// Returns the kinds of values that the unknown token at the given index was
+/// used with as `PdsValueKind` bit flags. Kinds are only recorded when
+/// diagnostics are enabled in the melt options, so 0 is returned otherwise.
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to a `MeltedBuffer`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_melt_unknown_token_value_kinds(
+    res: *const MeltedBuffer,
+    index: size_t,
+) -> u32 {
+    if res.is_null() {
+        return 0;
+    }
+
+    (*res)
+        .unknown_tokens()
+        .and_then(|x| x.context(index))
+        .map_or(0, |x| x.kinds())
 }
 
 /// Writes plaintext data into a provided buffer that is a given length.
@@ -105,7 +335,7 @@ pub unsafe extern "C" fn rakaly_melt_write_data(
     }
 
     match res {
-        MeltedBuffer::Verbatim => {}
+        MeltedBuffer::Verbatim | MeltedBuffer::Streamed { .. } => {}
         MeltedBuffer::Text { header, body } => {
             std::ptr::copy_nonoverlapping(header.as_ptr(), buffer.as_mut_ptr(), header.len());
             let offset = buffer.as_mut_ptr().add(header.len());
@@ -166,7 +396,7 @@ pub unsafe extern "C" fn rakaly_error_length(res: *const PdsError) -> c_int {
 /// convert it to a UTF-16 "unicode" afterwards.
 ///
 /// `-1` is returned if there are any errors, for example when passed a
-/// null pointer or a buffer of insufficient size.
+/// null pointer, a negative length, or a buffer of insufficient size.
 ///
 /// The buffer will not be null terminated.
 ///
@@ -180,7 +410,7 @@ pub unsafe extern "C" fn rakaly_error_write_data(
     buffer: *mut c_char,
     length: c_int,
 ) -> c_int {
-    if res.is_null() || buffer.is_null() {
+    if res.is_null() || buffer.is_null() || length < 0 {
         return -1;
     }
 
@@ -196,6 +426,90 @@ pub unsafe extern "C" fn rakaly_error_write_data(
     err.msg().len() as c_int
 }
 
+/// Returns a stable code that categorizes the error, so that callers don't
+/// need to match on the error message. Errors from a game's parser are
+/// categorized by their cause when possible and otherwise by the game.
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to a `PdsError`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_error_code(res: *const PdsError) -> PdsErrorCode {
+    if res.is_null() {
+        return PdsErrorCode::Unknown;
+    }
+
+    (*res).code()
+}
+
+/// Returns the byte offset into the parsed data where the error occurred. For
+/// compressed saves, the offset is into the decompressed data. `-1` is
+/// returned when the offset is not known.
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to a `PdsError`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_error_offset(res: *const PdsError) -> i64 {
+    if res.is_null() {
+        return -1;
+    }
+
+    (*res).offset().map_or(-1, |x| x as i64)
+}
+
+/// Calculate the number of bytes in the key path that was being melted when
+/// the error occurred. The length excludes null termination. The key path is
+/// only known for errors from melting into a returned buffer, or from melting
+/// with diagnostics enabled, so 0 is returned otherwise.
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to a `PdsError`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_error_key_path_length(res: *const PdsError) -> c_int {
+    if res.is_null() {
+        return 0;
+    }
+
+    (*res).key_path().map_or(0, |x| x.len() as c_int)
+}
+
+/// Write the key path that was being melted when the error occurred into a
+/// caller-provided buffer as a UTF-8 string, returning the number of bytes
+/// written. Keys are separated by `/`.
+///
+/// `-1` is returned if there are any errors, for example when passed a
+/// null pointer, a negative length, or a buffer of insufficient size.
+///
+/// The buffer will not be null terminated.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsError`
+/// - Given buffer must be at least the given length in size
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_error_write_key_path(
+    res: *const PdsError,
+    buffer: *mut c_char,
+    length: c_int,
+) -> c_int {
+    if res.is_null() || buffer.is_null() || length < 0 {
+        return -1;
+    }
+
+    let path = (*res).key_path().unwrap_or_default();
+    let buffer = std::slice::from_raw_parts_mut(buffer as *mut u8, length as usize);
+
+    if path.len() > buffer.len() {
+        return -1;
+    }
+
+    std::ptr::copy_nonoverlapping(path.as_ptr(), buffer.as_mut_ptr(), path.len());
+
+    path.len() as c_int
+}
+
 /// Destroys a `PdsError`
 ///
 /// # Safety
@@ -249,19 +563,27 @@ pub unsafe extern "C" fn rakaly_file_value(
 /// Returns a pointer to data that can decode a save's metadata. If a save does
 /// not have easily extractable metadata, then a null pointer is returned.
 ///
+/// Null is also returned when the metadata can't be parsed or the save is
+/// null, with the error available from `rakaly_last_error`.
+///
 /// # Safety
 ///
 /// - Must pass in a valid pointer to a `PdsFile`
 #[no_mangle]
 pub unsafe extern "C" fn rakaly_file_meta(ptr: *const PdsFile<'static>) -> *mut PdsMeta<'static> {
-    if ptr.is_null() {
-        return std::ptr::null_mut();
+    match file_meta(ptr) {
+        Ok(Some(meta)) => Box::into_raw(Box::new(meta)),
+        Ok(None) | Err(_) => std::ptr::null_mut(),
     }
+}
 
-    (*ptr)
-        .meta()
-        .map(|x| Box::into_raw(Box::new(x)))
-        .unwrap_or(std::ptr::null_mut())
+unsafe fn file_meta(ptr: *const PdsFile<'static>) -> Result<Option<PdsMeta<'static>>, LibError> {
+    try_ffi(|| {
+        let file = ptr
+            .as_ref()
+            .ok_or_else(|| LibError::InvalidArgument(String::from("file is null")))?;
+        file.meta()
+    })
 }
 
 /// Return the result of converting the metadata of a save to plaintext
@@ -271,22 +593,37 @@ pub unsafe extern "C" fn rakaly_file_meta(ptr: *const PdsFile<'static>) -> *mut
 /// - Must pass in a valid pointer to a `PdsMeta`
 #[no_mangle]
 pub unsafe extern "C" fn rakaly_file_meta_melt(ptr: *const PdsMeta) -> *mut MeltedBufferResult {
-    if ptr.is_null() {
-        return std::ptr::null_mut();
-    }
+    rakaly_file_meta_melt_with_options(ptr, std::ptr::null())
+}
 
-    let res = std::panic::catch_unwind(|| {
-        let result = match (*ptr).melt() {
-            Ok(x) => MeltedBufferResult::Ok(x),
-            Err(err) => MeltedBufferResult::Err(err),
-        };
-        Box::into_raw(Box::new(result))
-    });
+/// Return the result of converting the metadata of a save to plaintext with
+/// the given options. Default options are used when the options are null.
+///
+/// A null metadata pointer returns an error result.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsMeta`
+/// - Must pass in a valid pointer to `MeltOptions` or null
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_meta_melt_with_options(
+    ptr: *const PdsMeta,
+    options: *const MeltOptions,
+) -> *mut MeltedBufferResult {
+    melt_result(meta_melt(ptr, options))
+}
 
-    match res {
-        Ok(x) => x,
-        Err(_) => Box::into_raw(Box::new(MeltedBufferResult::Err(LibError::Panic))),
-    }
+unsafe fn meta_melt(
+    ptr: *const PdsMeta,
+    options: *const MeltOptions,
+) -> Result<MeltedBuffer, LibError> {
+    try_ffi(|| {
+        let meta = ptr
+            .as_ref()
+            .ok_or_else(|| LibError::InvalidArgument(String::from("metadata is null")))?;
+        let options = options.as_ref().cloned().unwrap_or_default();
+        meta.melt(&options)
+    })
 }
 
 /// Return the result of converting the save to plaintext
@@ -296,36 +633,155 @@ pub unsafe extern "C" fn rakaly_file_meta_melt(ptr: *const PdsMeta) -> *mut Melt
 /// - Must pass in a valid pointer to a `PdsFile`
 #[no_mangle]
 pub unsafe extern "C" fn rakaly_file_melt(ptr: *const PdsFile) -> *mut MeltedBufferResult {
-    if ptr.is_null() {
-        return std::ptr::null_mut();
-    }
+    rakaly_file_melt_with_options(ptr, std::ptr::null())
+}
 
-    let res = std::panic::catch_unwind(|| {
-        let result = match (*ptr).melt_file() {
-            Ok(x) => MeltedBufferResult::Ok(x),
-            Err(err) => MeltedBufferResult::Err(err),
-        };
-        Box::into_raw(Box::new(result))
-    });
+/// Return the result of converting the save to plaintext with the given
+/// options. Default options are used when the options are null.
+///
+/// A null save returns an error result.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsFile`
+/// - Must pass in a valid pointer to `MeltOptions` or null
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_melt_with_options(
+    ptr: *const PdsFile,
+    options: *const MeltOptions,
+) -> *mut MeltedBufferResult {
+    melt_result(file_melt(ptr, options))
+}
 
-    match res {
-        Ok(x) => x,
-        Err(_) => Box::into_raw(Box::new(MeltedBufferResult::Err(LibError::Panic))),
-    }
+unsafe fn file_melt(
+    ptr: *const PdsFile,
+    options: *const MeltOptions,
+) -> Result<MeltedBuffer, LibError> {
+    try_ffi(|| {
+        let file = ptr
+            .as_ref()
+            .ok_or_else(|| LibError::InvalidArgument(String::from("file is null")))?;
+        let options = options.as_ref().cloned().unwrap_or_default();
+        file.melt_file(&options)
+    })
 }
 
-/// Returns true if the melter needed to convert the binary input
+/// Return the result of converting the save to JSON. Binary saves are melted
+/// with the given options before conversion, and default options are used
+/// when the options are null. The JSON is written as UTF-8 for all games.
+///
+/// The duplicate keys mode is a `PdsJsonDuplicateKeys` value, and an error is
+/// returned for any other value.
+///
+/// A null save returns an error result.
 ///
 /// # Safety
 ///
-/// Must pass in a valid pointer to a `MeltedBuffer`
+/// - Must pass in a valid pointer to a `PdsFile`
+/// - Must pass in a valid pointer to `MeltOptions` or null
 #[no_mangle]
-pub unsafe extern "C" fn rakaly_file_is_binary(res: *const PdsFile) -> bool {
-    if res.is_null() {
-        return false;
-    }
+pub unsafe extern "C" fn rakaly_file_to_json(
+    ptr: *const PdsFile,
+    options: *const MeltOptions,
+    pretty: bool,
+    duplicate_keys: u32,
+) -> *mut MeltedBufferResult {
+    melt_result(file_to_json(ptr, options, pretty, duplicate_keys))
+}
 
-    (*res).is_binary()
+unsafe fn file_to_json(
+    ptr: *const PdsFile,
+    options: *const MeltOptions,
+    pretty: bool,
+    duplicate_keys: u32,
+) -> Result<MeltedBuffer, LibError> {
+    try_ffi(|| {
+        let file = ptr
+            .as_ref()
+            .ok_or_else(|| LibError::InvalidArgument(String::from("file is null")))?;
+        let options = options.as_ref().cloned().unwrap_or_default();
+        let duplicate_keys = PdsJsonDuplicateKeys::try_from(duplicate_keys)?;
+        let mut melted = Vec::new();
+        file.melt_all_to(&options, &mut melted)?;
+        let body = json::to_json(file.game(), &melted, pretty, duplicate_keys)?;
+        Ok(MeltedBuffer::Text {
+            header: Vec::new(),
+            body,
+        })
+    })
+}
+
+/// Return the result of converting the metadata of a save to JSON. Binary
+/// metadata is melted with the given options before conversion, and default
+/// options are used when the options are null. The JSON is written as UTF-8
+/// for all games. Only the metadata section of uncompressed saves is
+/// converted, not the gamestate that follows it.
+///
+/// The duplicate keys mode is a `PdsJsonDuplicateKeys` value, and an error is
+/// returned for any other value.
+///
+/// A null metadata pointer returns an error result.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsMeta`
+/// - Must pass in a valid pointer to `MeltOptions` or null
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_meta_to_json(
+    ptr: *const PdsMeta,
+    options: *const MeltOptions,
+    pretty: bool,
+    duplicate_keys: u32,
+) -> *mut MeltedBufferResult {
+    melt_result(meta_to_json(ptr, options, pretty, duplicate_keys))
+}
+
+unsafe fn meta_to_json(
+    ptr: *const PdsMeta,
+    options: *const MeltOptions,
+    pretty: bool,
+    duplicate_keys: u32,
+) -> Result<MeltedBuffer, LibError> {
+    try_ffi(|| {
+        let meta = ptr
+            .as_ref()
+            .ok_or_else(|| LibError::InvalidArgument(String::from("metadata is null")))?;
+        let options = options.as_ref().cloned().unwrap_or_default();
+        let duplicate_keys = PdsJsonDuplicateKeys::try_from(duplicate_keys)?;
+        let mut melted = Vec::new();
+        meta.melt_all_to(&options, &mut melted)?;
+        let body = json::to_json(meta.game(), &melted, pretty, duplicate_keys)?;
+        Ok(MeltedBuffer::Text {
+            header: Vec::new(),
+            body,
+        })
+    })
+}
+
+/// Return the result of extracting commonly needed fields (version, date,
+/// player, ironman, etc) from the save's metadata.
+///
+/// A null save returns an error result.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsFile`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_summary(ptr: *const PdsFile) -> *mut PdsSummaryResult {
+    let result = match file_summary(ptr) {
+        Ok(summary) => PdsSummaryResult::Ok(summary),
+        Err(err) => PdsSummaryResult::Err(err),
+    };
+    Box::into_raw(Box::new(result))
+}
+
+unsafe fn file_summary(ptr: *const PdsFile) -> Result<PdsSummary, LibError> {
+    try_ffi(|| {
+        let file = ptr
+            .as_ref()
+            .ok_or_else(|| LibError::InvalidArgument(String::from("file is null")))?;
+        file.summary()
+    })
 }
 
 /// Consume a result and return the underlying error. If the result does not
@@ -333,16 +789,16 @@ pub unsafe extern "C" fn rakaly_file_is_binary(res: *const PdsFile) -> bool {
 ///
 /// # Safety
 ///
-/// - Must pass in a valid pointer to a `MeltedBufferResult`
+/// - Must pass in a valid pointer to a `PdsSummaryResult`
 #[no_mangle]
-pub unsafe extern "C" fn rakaly_melt_error(ptr: *mut MeltedBufferResult) -> *mut PdsError {
+pub unsafe extern "C" fn rakaly_summary_error(ptr: *mut PdsSummaryResult) -> *mut PdsError {
     if ptr.is_null() {
         return std::ptr::null_mut();
     }
 
     match &*ptr {
-        MeltedBufferResult::Ok(_) => std::ptr::null_mut(),
-        MeltedBufferResult::Err(e) => {
+        PdsSummaryResult::Ok(_) => std::ptr::null_mut(),
+        PdsSummaryResult::Err(e) => {
             let res = Box::from_raw(ptr);
             let error = Box::into_raw(Box::new(PdsError::from(e)));
             drop(res);
@@ -356,189 +812,1916 @@ pub unsafe extern "C" fn rakaly_melt_error(ptr: *mut MeltedBufferResult) -> *mut
 ///
 /// # Safety
 ///
-/// - Must pass in a valid pointer to a `MeltedBufferResult`
+/// - Must pass in a valid pointer to a `PdsSummaryResult`
 #[no_mangle]
-pub unsafe extern "C" fn rakaly_melt_value(ptr: *mut MeltedBufferResult) -> *mut MeltedBuffer {
+pub unsafe extern "C" fn rakaly_summary_value(ptr: *mut PdsSummaryResult) -> *mut PdsSummary {
     if ptr.is_null() {
         return std::ptr::null_mut();
     }
 
     match &*ptr {
-        MeltedBufferResult::Ok(_) => {
+        PdsSummaryResult::Ok(_) => {
             let res = Box::from_raw(ptr);
             match *res {
-                MeltedBufferResult::Ok(buf) => Box::into_raw(Box::new(buf)),
-                MeltedBufferResult::Err(_) => unreachable_unchecked(),
+                PdsSummaryResult::Ok(summary) => Box::into_raw(Box::new(summary)),
+                PdsSummaryResult::Err(_) => unreachable_unchecked(),
             }
         }
-        MeltedBufferResult::Err(_) => std::ptr::null_mut(),
+        PdsSummaryResult::Err(_) => std::ptr::null_mut(),
     }
 }
 
-/// Initializes an EU4 save from a pointer the save data bytes and a number of
-/// those bytes.
+/// Destroys a `PdsSummary`
 ///
 /// # Safety
 ///
-/// The data is assumed to exist for the duration while the result of this
-/// function lives.
+/// Must pass in a valid pointer to a `PdsSummary`
 #[no_mangle]
-pub unsafe extern "C" fn rakaly_eu4_file(
-    data_ptr: *const c_char,
-    data_len: size_t,
-) -> *mut PdsFileResult<'static> {
-    let res = std::panic::catch_unwind(|| {
-        let dp = data_ptr as *const c_uchar;
-        let data = unsafe { std::slice::from_raw_parts(dp, data_len) };
-        let result = match eu4save::Eu4File::from_slice(data) {
-            Ok(x) => PdsFileResult::Ok(PdsFile::Eu4(x)),
-            Err(err) => PdsFileResult::Err(err.into()),
-        };
-        Box::into_raw(Box::new(result))
-    });
+pub unsafe extern "C" fn rakaly_free_summary(res: *mut PdsSummary) {
+    if !res.is_null() {
+        drop(Box::from_raw(res));
+    }
+}
 
-    match res {
-        Ok(x) => x,
-        Err(_) => Box::into_raw(Box::new(PdsFileResult::Err(LibError::Panic))),
+/// Returns the game of the summarized save
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsSummary`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_summary_game(ptr: *const PdsSummary) -> PdsGame {
+    if ptr.is_null() {
+        return PdsGame::Unknown;
     }
+
+    (*ptr).game()
 }
 
-/// Initializes an CK3 save from a pointer the save data bytes and a number of
-/// those bytes.
+/// Returns true if the summarized save is an ironman save
 ///
 /// # Safety
 ///
-/// The data is assumed to exist for the duration while the result of this
-/// function lives.
+/// - Must pass in a valid pointer to a `PdsSummary`
 #[no_mangle]
-pub unsafe extern "C" fn rakaly_ck3_file(
-    data_ptr: *const c_char,
-    data_len: size_t,
-) -> *mut PdsFileResult<'static> {
-    let res = std::panic::catch_unwind(|| {
-        let dp = data_ptr as *const c_uchar;
-        let data = unsafe { std::slice::from_raw_parts(dp, data_len) };
-        let result = match ck3save::Ck3File::from_slice(data) {
-            Ok(x) => PdsFileResult::Ok(PdsFile::Ck3(x)),
-            Err(err) => PdsFileResult::Err(err.into()),
-        };
-        Box::into_raw(Box::new(result))
-    });
+pub unsafe extern "C" fn rakaly_summary_ironman(ptr: *const PdsSummary) -> bool {
+    !ptr.is_null() && (*ptr).ironman()
+}
 
-    match res {
-        Ok(x) => x,
-        Err(_) => Box::into_raw(Box::new(PdsFileResult::Err(LibError::Panic))),
+/// Returns true if the summarized save is from a multiplayer game
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsSummary`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_summary_multiplayer(ptr: *const PdsSummary) -> bool {
+    !ptr.is_null() && (*ptr).multiplayer()
+}
+
+/// Returns true if the save recorded the given text field. The field is a
+/// `PdsSummaryField` value, and false is returned for any other value.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsSummary`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_summary_has_field(ptr: *const PdsSummary, field: u32) -> bool {
+    if ptr.is_null() {
+        return false;
+    }
+
+    match PdsSummaryField::try_from(field) {
+        Ok(field) => (*ptr).field(field).is_some(),
+        Err(err) => {
+            record_last_error(err);
+            false
+        }
     }
 }
 
-/// Initializes an Imperator save from a pointer the save data bytes and a number of
-/// those bytes.
+/// Calculate the number of bytes in the given text field. The length excludes
+/// null termination. Fields that the save did not record have a length of 0.
+///
+/// `-1` is returned when the field is not a `PdsSummaryField` value.
 ///
 /// # Safety
 ///
-/// The data is assumed to exist for the duration while the result of this
-/// function lives.
+/// - Must pass in a valid pointer to a `PdsSummary`
 #[no_mangle]
-pub unsafe extern "C" fn rakaly_imperator_file(
-    data_ptr: *const c_char,
-    data_len: size_t,
-) -> *mut PdsFileResult<'static> {
-    let res = std::panic::catch_unwind(|| {
-        let dp = data_ptr as *const c_uchar;
-        let data = unsafe { std::slice::from_raw_parts(dp, data_len) };
-        let result = match imperator_save::ImperatorFile::from_slice(data) {
-            Ok(x) => PdsFileResult::Ok(PdsFile::Imperator(x)),
-            Err(err) => PdsFileResult::Err(err.into()),
-        };
-        Box::into_raw(Box::new(result))
+pub unsafe extern "C" fn rakaly_summary_field_length(ptr: *const PdsSummary, field: u32) -> c_int {
+    if ptr.is_null() {
+        return 0;
+    }
+
+    match PdsSummaryField::try_from(field) {
+        Ok(field) => (*ptr).field(field).map_or(0, |x| x.len() as c_int),
+        Err(err) => {
+            record_last_error(err);
+            -1
+        }
+    }
+}
+
+/// Write the given text field into a caller-provided buffer as a UTF-8
+/// string, returning the number of bytes written.
+///
+/// `-1` is returned if there are any errors, for example when passed a
+/// null pointer, a field that is not a `PdsSummaryField` value, a negative
+/// length, or a buffer of insufficient size.
+///
+/// The buffer will not be null terminated.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsSummary`
+/// - Given buffer must be at least the given length in size
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_summary_write_field(
+    ptr: *const PdsSummary,
+    field: u32,
+    buffer: *mut c_char,
+    length: c_int,
+) -> c_int {
+    if ptr.is_null() || buffer.is_null() || length < 0 {
+        return -1;
+    }
+
+    let field = match PdsSummaryField::try_from(field) {
+        Ok(field) => field,
+        Err(err) => {
+            record_last_error(err);
+            return -1;
+        }
+    };
+
+    let value = (*ptr).field(field).unwrap_or_default();
+    let buffer = std::slice::from_raw_parts_mut(buffer as *mut u8, length as usize);
+
+    if value.len() > buffer.len() {
+        return -1;
+    }
+
+    std::ptr::copy_nonoverlapping(value.as_ptr(), buffer.as_mut_ptr(), value.len());
+
+    value.len() as c_int
+}
+
+/// Creates melt options with the defaults used by `rakaly_file_melt`: verbatim
+/// output and unknown tokens written as hex strings.
+#[no_mangle]
+pub extern "C" fn rakaly_melt_options_new() -> *mut MeltOptions {
+    Box::into_raw(Box::new(MeltOptions::default()))
+}
+
+/// Sets whether the save is melted verbatim. When disabled, the ironman flag
+/// is removed from the melted output unless it is kept with
+/// `rakaly_melt_options_keep_ironman`.
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to `MeltOptions`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_melt_options_verbatim(options: *mut MeltOptions, verbatim: bool) {
+    if let Some(options) = options.as_mut() {
+        options.set_verbatim(verbatim);
+    }
+}
+
+/// Sets whether the ironman flag is kept in the melted output when the save is
+/// not melted verbatim. Only the ironman flag is affected.
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to `MeltOptions`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_melt_options_keep_ironman(
+    options: *mut MeltOptions,
+    keep_ironman: bool,
+) {
+    if let Some(options) = options.as_mut() {
+        options.set_keep_ironman(keep_ironman);
+    }
+}
+
+/// Sets how the melter handles binary tokens that can't be resolved to text.
+/// The strategy is a `PdsFailedResolve` value.
+///
+/// Returns false and leaves the options unchanged when the strategy is not a
+/// `PdsFailedResolve` value, with the error available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to `MeltOptions`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_melt_options_on_failed_resolve(
+    options: *mut MeltOptions,
+    strategy: u32,
+) -> bool {
+    let strategy = match PdsFailedResolve::try_from(strategy) {
+        Ok(strategy) => strategy,
+        Err(err) => {
+            record_last_error(err);
+            return false;
+        }
+    };
+
+    match options.as_mut() {
+        Some(options) => {
+            options.set_on_failed_resolve(strategy);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Sets whether unknown tokens are diagnosed. When enabled, the key path and
+/// the kinds of values of every unknown token are recorded for
+/// `rakaly_melt_unknown_token_write_path` and
+/// `rakaly_melt_unknown_token_value_kinds`. Diagnostics follow the unknown
+/// tokens as they are written to the melted output, so they require the
+/// default `PdsFailedResolve_Stringify` strategy and melting fails with
+/// `PdsErrorCode_InvalidArgument` under any other strategy.
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to `MeltOptions`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_melt_options_diagnostics(
+    options: *mut MeltOptions,
+    diagnostics: bool,
+) {
+    if let Some(options) = options.as_mut() {
+        options.set_diagnostics(diagnostics);
+    }
+}
+
+/// Destroys `MeltOptions`
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to `MeltOptions`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_free_melt_options(options: *mut MeltOptions) {
+    if !options.is_null() {
+        drop(Box::from_raw(options));
+    }
+}
+
+/// A caller provided function that receives melted output in chunks.
+///
+/// The function is given the `user_data` pointer that was passed alongside it
+/// and should return 0 if the chunk was successfully consumed. Any other
+/// return value aborts the melt.
+pub type RakalyWriteCallback =
+    Option<unsafe extern "C" fn(user_data: *mut c_void, data: *const c_char, len: size_t) -> c_int>;
+
+/// Converts the save to plaintext with the given options, streaming the
+/// output through the given callback instead of buffering it in memory.
+/// Default options are used when the options are null.
+///
+/// Unlike `rakaly_file_melt`, saves that are already plaintext are written
+/// through the callback too, so the callback always receives the complete
+/// melted output. The returned `MeltedBuffer` holds no data but can still be
+/// queried for unknown tokens.
+///
+/// An error result is returned when the save or callback is null. Once the
+/// callback returns a non-zero value it is not called again, and output that
+/// was buffered but not yet given to the callback is discarded.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsFile`
+/// - Must pass in a valid pointer to `MeltOptions` or null
+/// - The callback must be safe to call with the given user data
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_melt_to_writer(
+    ptr: *const PdsFile,
+    options: *const MeltOptions,
+    write_cb: RakalyWriteCallback,
+    user_data: *mut c_void,
+) -> *mut MeltedBufferResult {
+    melt_result(melt_to_writer(ptr, options, write_cb, user_data))
+}
+
+unsafe fn melt_to_writer(
+    ptr: *const PdsFile,
+    options: *const MeltOptions,
+    write_cb: RakalyWriteCallback,
+    user_data: *mut c_void,
+) -> Result<MeltedBuffer, LibError> {
+    try_ffi(|| melt_to_callback(ptr, options, write_cb, user_data).map(MeltOutcome::into_streamed))
+}
+
+unsafe fn melt_to_callback(
+    ptr: *const PdsFile,
+    options: *const MeltOptions,
+    write_cb: RakalyWriteCallback,
+    user_data: *mut c_void,
+) -> Result<MeltOutcome, LibError> {
+    let file = ptr
+        .as_ref()
+        .ok_or_else(|| LibError::InvalidArgument(String::from("file is null")))?;
+    let write_cb = write_cb
+        .ok_or_else(|| LibError::InvalidArgument(String::from("write callback is null")))?;
+    let options = options.as_ref().cloned().unwrap_or_default();
+
+    let writer = CallbackWriter::new(write_cb, user_data);
+    let mut output = BufWriter::with_capacity(64 * 1024, writer);
+    let result = file.melt_all_to(&options, &mut output);
+
+    // Dropping the writer would flush what is buffered, so after an error the
+    // buffer is discarded instead of being given to a callback that failed or
+    // to one whose output is incomplete
+    if result.is_err() {
+        let _ = output.into_parts();
+    }
+
+    result
+}
+
+/// Converts the save to plaintext with the given options and writes it to
+/// the file at the given path, replacing any existing file. Saves that are
+/// already plaintext are copied, so the written file is always complete.
+/// Default options are used when the options are null.
+///
+/// The output is written to a temporary file next to the destination that is
+/// renamed over the destination once the melt succeeds, so a failed melt
+/// leaves any existing file untouched.
+///
+/// The path must be a null terminated UTF-8 string.
+///
+/// Returns null on success, otherwise an error that must be freed with
+/// `rakaly_free_error`. A null save or path is an error.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsFile`
+/// - Must pass in a valid pointer to `MeltOptions` or null
+/// - Must pass in a valid pointer to a null terminated string
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_melt_to_path(
+    ptr: *const PdsFile,
+    options: *const MeltOptions,
+    path: *const c_char,
+) -> *mut PdsError {
+    melt_to_path(path, || {
+        let file = ptr
+            .as_ref()
+            .ok_or_else(|| LibError::InvalidArgument(String::from("file is null")))?;
+        let options = options.as_ref().cloned().unwrap_or_default();
+        Ok(move |out: &mut BufWriter<File>| file.melt_all_to(&options, out))
+    })
+}
+
+/// Converts the metadata of a save to plaintext with the given options and
+/// writes it to the file at the given path, replacing any existing file.
+/// Default options are used when the options are null.
+///
+/// The output is written to a temporary file next to the destination that is
+/// renamed over the destination once the melt succeeds, so a failed melt
+/// leaves any existing file untouched.
+///
+/// The path must be a null terminated UTF-8 string.
+///
+/// Returns null on success, otherwise an error that must be freed with
+/// `rakaly_free_error`. A null metadata or path is an error.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsMeta`
+/// - Must pass in a valid pointer to `MeltOptions` or null
+/// - Must pass in a valid pointer to a null terminated string
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_meta_melt_to_path(
+    ptr: *const PdsMeta,
+    options: *const MeltOptions,
+    path: *const c_char,
+) -> *mut PdsError {
+    melt_to_path(path, || {
+        let meta = ptr
+            .as_ref()
+            .ok_or_else(|| LibError::InvalidArgument(String::from("metadata is null")))?;
+        let options = options.as_ref().cloned().unwrap_or_default();
+        Ok(move |out: &mut BufWriter<File>| meta.melt_all_to(&options, out))
+    })
+}
+
+/// Melts into a temporary file next to the path and renames it to the path
+/// once the melt succeeds. The melt is prepared before any file is created,
+/// so invalid arguments are reported without touching the file system.
+unsafe fn melt_to_path<P, F>(path: *const c_char, prepare: P) -> *mut PdsError
+where
+    P: FnOnce() -> Result<F, LibError> + UnwindSafe,
+    F: FnOnce(&mut BufWriter<File>) -> Result<MeltOutcome, LibError>,
+{
+    let res = try_ffi(|| {
+        let path = c_path(path)?;
+        let melt = prepare()?;
+        let (temp, file) = TempFile::create(path)?;
+        let mut output = BufWriter::new(file);
+        melt(&mut output)?;
+        output
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        temp.persist(path)
     });
 
     match res {
-        Ok(x) => x,
-        Err(_) => Box::into_raw(Box::new(PdsFileResult::Err(LibError::Panic))),
+        Ok(_) => std::ptr::null_mut(),
+        Err(err) => Box::into_raw(Box::new(PdsError::from(&err))),
     }
 }
 
-/// Initializes an HOI4 save from a pointer the save data bytes and a number of
-/// those bytes.
+/// A file in the same directory as a destination that is removed when
+/// dropped unless it was renamed to the destination
+struct TempFile {
+    path: Option<PathBuf>,
+}
+
+impl TempFile {
+    fn create(dest: &Path) -> Result<(Self, File), LibError> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let name = dest.file_name().ok_or_else(|| {
+            LibError::InvalidArgument(format!("{} is not a file path", dest.display()))
+        })?;
+
+        let mut temp_name = OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(
+            ".{}-{}.tmp",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let path = dest.with_file_name(temp_name);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok((TempFile { path: Some(path) }, file))
+    }
+
+    fn persist(mut self, dest: &Path) -> Result<(), LibError> {
+        let path = self.path.take().expect("temporary file to exist");
+        if let Err(err) = std::fs::rename(&path, dest) {
+            let _ = std::fs::remove_file(&path);
+            return Err(err.into());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+unsafe fn c_path<'a>(path: *const c_char) -> Result<&'a Path, LibError> {
+    if path.is_null() {
+        return Err(LibError::InvalidArgument(String::from("path is null")));
+    }
+
+    let path = CStr::from_ptr(path).to_str().map_err(|_| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "path is not valid UTF-8")
+    })?;
+    Ok(Path::new(path))
+}
+
+/// Loads binary token names for the given game from a pointer to the token
+/// data and the number of bytes. The tokens are used for all subsequent melts
+/// in the process. A loaded token takes precedence over an embedded token with
+/// the same id, so tokens from a new game patch can be used without a new
+/// library release.
+///
+/// Each line of the data is a token id, in decimal or hexadecimal prefixed
+/// with `0x`, followed by whitespace and the token name. An id may only be
+/// given once. Passing a null pointer or no data with `PdsTokensMode_Extend`
+/// restores the embedded tokens, while doing so with `PdsTokensMode_Replace`
+/// is an error.
+///
+/// The game is a `PdsGame` value other than `PdsGame_Unknown`, and the mode is
+/// a `PdsTokensMode` value that decides if the tokens extend or replace the
+/// embedded and previously loaded tokens.
+///
+/// Returns null on success, otherwise an error that must be freed with
+/// `rakaly_free_error`. Previously loaded tokens are kept when the data is
+/// invalid.
 ///
 /// # Safety
 ///
-/// The data is assumed to exist for the duration while the result of this
-/// function lives.
+/// - Given data must be at least the given length in size
 #[no_mangle]
-pub unsafe extern "C" fn rakaly_hoi4_file(
+pub unsafe extern "C" fn rakaly_set_tokens(
+    game: u32,
     data_ptr: *const c_char,
     data_len: size_t,
-) -> *mut PdsFileResult<'static> {
-    let res = std::panic::catch_unwind(|| {
-        let dp = data_ptr as *const c_uchar;
-        let data = unsafe { std::slice::from_raw_parts(dp, data_len) };
-        let result = match hoi4save::Hoi4File::from_slice(data) {
-            Ok(x) => PdsFileResult::Ok(PdsFile::Hoi4(x)),
-            Err(err) => PdsFileResult::Err(err.into()),
+    mode: u32,
+) -> *mut PdsError {
+    let res = unwind::catch_unwind(|| {
+        let data = if data_ptr.is_null() {
+            &[][..]
+        } else {
+            std::slice::from_raw_parts(data_ptr as *const u8, data_len)
         };
-        Box::into_raw(Box::new(result))
+        let game = PdsGame::try_from(game)?;
+        let mode = PdsTokensMode::try_from(mode)?;
+        tokens::load_tokens(game, data, mode)
     });
 
     match res {
-        Ok(x) => x,
-        Err(_) => Box::into_raw(Box::new(PdsFileResult::Err(LibError::Panic))),
+        Ok(Ok(())) => std::ptr::null_mut(),
+        Ok(Err(err)) => Box::into_raw(Box::new(PdsError::from(&record_last_error(err)))),
+        Err(err) => Box::into_raw(Box::new(PdsError::from(&record_last_error(err)))),
     }
 }
 
-/// Initializes a Vic3 save from a pointer the save data bytes and a number of
-/// those bytes.
+/// Loads binary token names for the given game from the file at the given
+/// path. See `rakaly_set_tokens` for how the tokens are used and formatted.
+///
+/// The path must be a null terminated UTF-8 string.
+///
+/// Returns null on success, otherwise an error that must be freed with
+/// `rakaly_free_error`. A null path is an error.
 ///
 /// # Safety
 ///
-/// The data is assumed to exist for the duration while the result of this
-/// function lives.
+/// - Must pass in a valid pointer to a null terminated string
 #[no_mangle]
-pub unsafe extern "C" fn rakaly_vic3_file(
-    data_ptr: *const c_char,
-    data_len: size_t,
-) -> *mut PdsFileResult<'static> {
-    let res = std::panic::catch_unwind(|| {
-        let dp = data_ptr as *const c_uchar;
-        let data = unsafe { std::slice::from_raw_parts(dp, data_len) };
-        let result = match vic3save::Vic3File::from_slice(data) {
-            Ok(x) => PdsFileResult::Ok(PdsFile::Vic3(x)),
-            Err(err) => PdsFileResult::Err(err.into()),
-        };
-        Box::into_raw(Box::new(result))
+pub unsafe extern "C" fn rakaly_load_tokens_path(
+    game: u32,
+    path: *const c_char,
+    mode: u32,
+) -> *mut PdsError {
+    let res = unwind::catch_unwind(|| {
+        let game = PdsGame::try_from(game)?;
+        let mode = PdsTokensMode::try_from(mode)?;
+        let path = c_path(path)?;
+        let data = std::fs::read(path)?;
+        tokens::load_tokens(game, &data, mode)
     });
 
     match res {
-        Ok(x) => x,
-        Err(_) => Box::into_raw(Box::new(PdsFileResult::Err(LibError::Panic))),
+        Ok(Ok(())) => std::ptr::null_mut(),
+        Ok(Err(err)) => Box::into_raw(Box::new(PdsError::from(&record_last_error(err)))),
+        Err(err) => Box::into_raw(Box::new(PdsError::from(&record_last_error(err)))),
     }
 }
 
-/// Initializes an EU5 save from a pointer the save data bytes and a number of
-/// those bytes.
+/// Returns the version of librakaly as a null terminated string, for example
+/// `0.12.7`. The string is static and must not be freed.
+#[no_mangle]
+pub extern "C" fn rakaly_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+/// Describes the binary tokens that were embedded in the library for the
+/// given game when it was built: the number of tokens, a 64 bit FNV-1a hash
+/// of the token file, and if the token file was empty. A library built
+/// without tokens can't melt binary saves of that game. Tokens loaded with
+/// `rakaly_set_tokens` are not included.
+///
+/// An empty description is returned for `PdsGame_Unknown` and for values that
+/// are not a `PdsGame`.
+#[no_mangle]
+pub extern "C" fn rakaly_embedded_tokens(game: u32) -> PdsEmbeddedTokens {
+    match PdsGame::try_from(game) {
+        Ok(game) => tokens::embedded_tokens(game),
+        Err(err) => {
+            record_last_error(err);
+            PdsEmbeddedTokens::default()
+        }
+    }
+}
+
+/// Returns the binary token id of the field with the given name for a game,
+/// using the embedded tokens and any tokens loaded with `rakaly_set_tokens`.
+///
+/// The name must be a null terminated UTF-8 string.
+///
+/// `-1` is returned when the game has no token with the name. It is also
+/// returned with the last error set when the game is not a `PdsGame` value or
+/// the name is null or not valid UTF-8.
 ///
 /// # Safety
 ///
-/// The data is assumed to exist for the duration while the result of this
-/// function lives.
+/// - Must pass in a valid pointer to a null terminated string
 #[no_mangle]
-pub unsafe extern "C" fn rakaly_eu5_file(
-    data_ptr: *const c_char,
-    data_len: size_t,
-) -> *mut PdsFileResult<'static> {
-    let res = std::panic::catch_unwind(|| {
-        let dp = data_ptr as *const c_uchar;
-        let data = unsafe { std::slice::from_raw_parts(dp, data_len) };
-        let result = match eu5save::Eu5File::from_slice(data) {
-            Ok(x) => PdsFileResult::Ok(PdsFile::Eu5(x)),
-            Err(err) => PdsFileResult::Err(err.into()),
-        };
-        Box::into_raw(Box::new(result))
+pub unsafe extern "C" fn rakaly_token_lookup(game: u32, name: *const c_char) -> c_int {
+    if name.is_null() {
+        record_last_error(LibError::InvalidArgument(String::from("name is null")));
+        return -1;
+    }
+
+    let res = unwind::catch_unwind(|| {
+        let name = CStr::from_ptr(name)
+            .to_str()
+            .map_err(|_| {
+                record_last_error(LibError::InvalidArgument(String::from(
+                    "name is not valid UTF-8",
+                )))
+            })
+            .ok()?;
+        PdsGame::try_from(game)
+            .and_then(tokens::tokens_resolver)
+            .map_err(record_last_error)
+            .ok()?
+            .id(name)
+    });
+
+    match res {
+        Ok(Some(id)) => c_int::from(id),
+        Ok(None) => -1,
+        Err(err) => {
+            record_last_error(err);
+            -1
+        }
+    }
+}
+
+/// Calculate the number of bytes in the name of the given binary token for a
+/// game. The length excludes null termination.
+///
+/// `-1` is returned when the game has no token with the id or is not a
+/// `PdsGame` value.
+#[no_mangle]
+pub extern "C" fn rakaly_token_name_length(game: u32, id: u16) -> c_int {
+    let res = unwind::catch_unwind(|| {
+        let resolver = PdsGame::try_from(game)
+            .and_then(tokens::tokens_resolver)
+            .map_err(record_last_error)
+            .ok()?;
+        resolver.resolve(id).map(str::len)
     });
 
     match res {
-        Ok(x) => x,
-        Err(_) => Box::into_raw(Box::new(PdsFileResult::Err(LibError::Panic))),
+        Ok(Some(len)) => len as c_int,
+        Ok(None) => -1,
+        Err(err) => {
+            record_last_error(err);
+            -1
+        }
+    }
+}
+
+/// Write the name of the given binary token for a game into a caller-provided
+/// buffer as a UTF-8 string, returning the number of bytes written. The
+/// embedded tokens and any tokens loaded with `rakaly_set_tokens` are used.
+///
+/// `-1` is returned when the game has no token with the id or if there are any
+/// errors, for example when passed a null pointer, a game that is not a
+/// `PdsGame` value, a negative length, or a buffer of insufficient size.
+///
+/// The buffer will not be null terminated.
+///
+/// # Safety
+///
+/// - Given buffer must be at least the given length in size
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_token_name(
+    game: u32,
+    id: u16,
+    buffer: *mut c_char,
+    length: c_int,
+) -> c_int {
+    if buffer.is_null() {
+        record_last_error(LibError::InvalidArgument(String::from("buffer is null")));
+        return -1;
+    }
+
+    if length < 0 {
+        record_last_error(LibError::InvalidArgument(String::from(
+            "length is negative",
+        )));
+        return -1;
+    }
+
+    let res = unwind::catch_unwind(|| {
+        let resolver = PdsGame::try_from(game)
+            .and_then(tokens::tokens_resolver)
+            .map_err(record_last_error)
+            .ok()?;
+        let name = resolver.resolve(id)?;
+        let buffer = std::slice::from_raw_parts_mut(buffer as *mut u8, length as usize);
+        if name.len() > buffer.len() {
+            return None;
+        }
+
+        std::ptr::copy_nonoverlapping(name.as_ptr(), buffer.as_mut_ptr(), name.len());
+        Some(name.len())
+    });
+
+    match res {
+        Ok(Some(len)) => len as c_int,
+        Ok(None) => -1,
+        Err(err) => {
+            record_last_error(err);
+            -1
+        }
+    }
+}
+
+/// Returns the game that the save file belongs to
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsFile`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_game(ptr: *const PdsFile) -> PdsGame {
+    if ptr.is_null() {
+        return PdsGame::Unknown;
+    }
+
+    (*ptr).game()
+}
+
+/// Returns true if the melter needed to convert the binary input
+///
+/// # Safety
+///
+/// Must pass in a valid pointer to a `MeltedBuffer`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_is_binary(res: *const PdsFile) -> bool {
+    if res.is_null() {
+        return false;
+    }
+
+    (*res).is_binary()
+}
+
+/// Returns how the save file is encoded, distinguishing between plaintext and
+/// binary saves as well as whether they are compressed.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsFile`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_encoding(ptr: *const PdsFile) -> PdsEncoding {
+    if ptr.is_null() {
+        return PdsEncoding::Unknown;
+    }
+
+    (*ptr).encoding()
+}
+
+/// Consume a result and return the underlying error. If the result does not
+/// encompass an error, the result is not consumed.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `MeltedBufferResult`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_melt_error(ptr: *mut MeltedBufferResult) -> *mut PdsError {
+    if ptr.is_null() {
+        return std::ptr::null_mut();
+    }
+
+    match &*ptr {
+        MeltedBufferResult::Ok(_) => std::ptr::null_mut(),
+        MeltedBufferResult::Err(e) => {
+            let res = Box::from_raw(ptr);
+            let error = Box::into_raw(Box::new(PdsError::from(e)));
+            drop(res);
+            error
+        }
+    }
+}
+
+/// Consume a result and return the underlying value. If the result does not
+/// encompass a value, the result is not consumed.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `MeltedBufferResult`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_melt_value(ptr: *mut MeltedBufferResult) -> *mut MeltedBuffer {
+    if ptr.is_null() {
+        return std::ptr::null_mut();
+    }
+
+    match &*ptr {
+        MeltedBufferResult::Ok(_) => {
+            let res = Box::from_raw(ptr);
+            match *res {
+                MeltedBufferResult::Ok(buf) => Box::into_raw(Box::new(buf)),
+                MeltedBufferResult::Err(_) => unreachable_unchecked(),
+            }
+        }
+        MeltedBufferResult::Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Initializes a save from a pointer the save data bytes and a number of those
+/// bytes. The game is detected from the data, so this can be used in place of
+/// the game specific constructors when the game is not known ahead of time.
+///
+/// If no game recognizes the data, the returned error lists why each game
+/// rejected it. CK3, Imperator, Vic3, and EU5 saves share an envelope and are
+/// told apart by their zip entries and metadata keys, so a save that matches
+/// several of them equally is not recognized and should be parsed with its
+/// game specific constructor.
+///
+/// Null data returns an error result.
+///
+/// # Safety
+///
+/// The data is assumed to exist for the duration while the result of this
+/// function lives.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFileResult<'static> {
+    file_result(parse_file(PdsGame::Unknown, data_ptr, data_len))
+}
+
+/// Initializes a save from a copy of the save data, detecting the game from
+/// the data. As the save owns the copy, the given data can be freed as soon
+/// as this function returns.
+///
+/// # Safety
+///
+/// The data must be valid for the duration of this function.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_owned(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFileResult<'static> {
+    file_result(parse_owned_file(PdsGame::Unknown, data_ptr, data_len))
+}
+
+/// Initializes a save from a file path, detecting the game from the data. The
+/// file is memory mapped rather than read into memory, and the mapping is
+/// kept alive by the returned save.
+///
+/// The path must be a null terminated UTF-8 string. A null path returns an
+/// error result.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a null terminated string
+/// - The file must not be modified while the result of this function lives
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_open_path(path: *const c_char) -> *mut PdsFileResult<'static> {
+    file_result(open_file_path(PdsGame::Unknown, path))
+}
+
+/// Initializes an EU4 save from a pointer the save data bytes and a number of
+/// those bytes.
+///
+/// # Safety
+///
+/// The data is assumed to exist for the duration while the result of this
+/// function lives.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_eu4_file(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFileResult<'static> {
+    file_result(parse_file(PdsGame::Eu4, data_ptr, data_len))
+}
+
+/// Initializes an EU4 save from a copy of the save data. As the save owns the
+/// copy, the given data can be freed as soon as this function returns.
+///
+/// # Safety
+///
+/// The data must be valid for the duration of this function.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_eu4_file_owned(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFileResult<'static> {
+    file_result(parse_owned_file(PdsGame::Eu4, data_ptr, data_len))
+}
+
+/// Initializes an EU4 save from a file path. The file is memory mapped
+/// rather than read into memory, and the mapping is kept alive by the returned
+/// save.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a null terminated string
+/// - The file must not be modified while the result of this function lives
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_eu4_file_open_path(
+    path: *const c_char,
+) -> *mut PdsFileResult<'static> {
+    file_result(open_file_path(PdsGame::Eu4, path))
+}
+
+/// Initializes an CK3 save from a pointer the save data bytes and a number of
+/// those bytes.
+///
+/// # Safety
+///
+/// The data is assumed to exist for the duration while the result of this
+/// function lives.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_ck3_file(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFileResult<'static> {
+    file_result(parse_file(PdsGame::Ck3, data_ptr, data_len))
+}
+
+/// Initializes a CK3 save from a copy of the save data. As the save owns the
+/// copy, the given data can be freed as soon as this function returns.
+///
+/// # Safety
+///
+/// The data must be valid for the duration of this function.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_ck3_file_owned(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFileResult<'static> {
+    file_result(parse_owned_file(PdsGame::Ck3, data_ptr, data_len))
+}
+
+/// Initializes a CK3 save from a file path. The file is memory mapped
+/// rather than read into memory, and the mapping is kept alive by the returned
+/// save.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a null terminated string
+/// - The file must not be modified while the result of this function lives
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_ck3_file_open_path(
+    path: *const c_char,
+) -> *mut PdsFileResult<'static> {
+    file_result(open_file_path(PdsGame::Ck3, path))
+}
+
+/// Initializes an Imperator save from a pointer the save data bytes and a number of
+/// those bytes.
+///
+/// # Safety
+///
+/// The data is assumed to exist for the duration while the result of this
+/// function lives.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_imperator_file(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFileResult<'static> {
+    file_result(parse_file(PdsGame::Imperator, data_ptr, data_len))
+}
+
+/// Initializes an Imperator save from a copy of the save data. As the save
+/// owns the copy, the given data can be freed as soon as this function
+/// returns.
+///
+/// # Safety
+///
+/// The data must be valid for the duration of this function.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_imperator_file_owned(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFileResult<'static> {
+    file_result(parse_owned_file(PdsGame::Imperator, data_ptr, data_len))
+}
+
+/// Initializes an Imperator save from a file path. The file is memory mapped
+/// rather than read into memory, and the mapping is kept alive by the returned
+/// save.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a null terminated string
+/// - The file must not be modified while the result of this function lives
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_imperator_file_open_path(
+    path: *const c_char,
+) -> *mut PdsFileResult<'static> {
+    file_result(open_file_path(PdsGame::Imperator, path))
+}
+
+/// Initializes an HOI4 save from a pointer the save data bytes and a number of
+/// those bytes.
+///
+/// # Safety
+///
+/// The data is assumed to exist for the duration while the result of this
+/// function lives.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_hoi4_file(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFileResult<'static> {
+    file_result(parse_file(PdsGame::Hoi4, data_ptr, data_len))
+}
+
+/// Initializes an HOI4 save from a copy of the save data. As the save owns the
+/// copy, the given data can be freed as soon as this function returns.
+///
+/// # Safety
+///
+/// The data must be valid for the duration of this function.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_hoi4_file_owned(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFileResult<'static> {
+    file_result(parse_owned_file(PdsGame::Hoi4, data_ptr, data_len))
+}
+
+/// Initializes an HOI4 save from a file path. The file is memory mapped
+/// rather than read into memory, and the mapping is kept alive by the returned
+/// save.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a null terminated string
+/// - The file must not be modified while the result of this function lives
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_hoi4_file_open_path(
+    path: *const c_char,
+) -> *mut PdsFileResult<'static> {
+    file_result(open_file_path(PdsGame::Hoi4, path))
+}
+
+/// Initializes a Vic3 save from a pointer the save data bytes and a number of
+/// those bytes.
+///
+/// # Safety
+///
+/// The data is assumed to exist for the duration while the result of this
+/// function lives.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_vic3_file(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFileResult<'static> {
+    file_result(parse_file(PdsGame::Vic3, data_ptr, data_len))
+}
+
+/// Initializes a Vic3 save from a copy of the save data. As the save owns the
+/// copy, the given data can be freed as soon as this function returns.
+///
+/// # Safety
+///
+/// The data must be valid for the duration of this function.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_vic3_file_owned(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFileResult<'static> {
+    file_result(parse_owned_file(PdsGame::Vic3, data_ptr, data_len))
+}
+
+/// Initializes a Vic3 save from a file path. The file is memory mapped
+/// rather than read into memory, and the mapping is kept alive by the returned
+/// save.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a null terminated string
+/// - The file must not be modified while the result of this function lives
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_vic3_file_open_path(
+    path: *const c_char,
+) -> *mut PdsFileResult<'static> {
+    file_result(open_file_path(PdsGame::Vic3, path))
+}
+
+/// Initializes an EU5 save from a pointer the save data bytes and a number of
+/// those bytes.
+///
+/// # Safety
+///
+/// The data is assumed to exist for the duration while the result of this
+/// function lives.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_eu5_file(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFileResult<'static> {
+    file_result(parse_file(PdsGame::Eu5, data_ptr, data_len))
+}
+
+/// Initializes an EU5 save from a copy of the save data. As the save owns the
+/// copy, the given data can be freed as soon as this function returns.
+///
+/// # Safety
+///
+/// The data must be valid for the duration of this function.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_eu5_file_owned(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFileResult<'static> {
+    file_result(parse_owned_file(PdsGame::Eu5, data_ptr, data_len))
+}
+
+/// Initializes an EU5 save from a file path. The file is memory mapped
+/// rather than read into memory, and the mapping is kept alive by the returned
+/// save.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a null terminated string
+/// - The file must not be modified while the result of this function lives
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_eu5_file_open_path(
+    path: *const c_char,
+) -> *mut PdsFileResult<'static> {
+    file_result(open_file_path(PdsGame::Eu5, path))
+}
+
+/// Runs the body of a C function, converting a panic into an error and
+/// recording any error as the calling thread's last error
+fn try_ffi<T, F>(f: F) -> Result<T, LibError>
+where
+    F: FnOnce() -> Result<T, LibError> + UnwindSafe,
+{
+    unwind::catch_unwind(f)
+        .and_then(|x| x)
+        .map_err(record_last_error)
+}
+
+/// Returns a value to a caller that checks `rakaly_last_error` on null
+fn or_null<T>(result: Result<T, LibError>) -> *mut T {
+    match result {
+        Ok(x) => Box::into_raw(Box::new(x)),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+fn melt_result(result: Result<MeltedBuffer, LibError>) -> *mut MeltedBufferResult {
+    let result = match result {
+        Ok(x) => MeltedBufferResult::Ok(x),
+        Err(err) => MeltedBufferResult::Err(err),
+    };
+    Box::into_raw(Box::new(result))
+}
+
+fn file_result(result: Result<PdsFile<'static>, LibError>) -> *mut PdsFileResult<'static> {
+    let result = match result {
+        Ok(x) => PdsFileResult::Ok(x),
+        Err(err) => PdsFileResult::Err(err),
+    };
+    Box::into_raw(Box::new(result))
+}
+
+/// Parses a save for the caller, logging what was parsed
+fn parse_with<F>(f: F) -> Result<PdsFile<'static>, LibError>
+where
+    F: FnOnce() -> Result<PdsFile<'static>, LibError> + UnwindSafe,
+{
+    let result = try_ffi(f);
+    match &result {
+        Ok(x) => log::info(format_args!(
+            "parsed {} save encoded as {:?}",
+            x.game(),
+            x.encoding()
+        )),
+        Err(err) => log::warn(format_args!("failed to parse save: {}", err)),
+    }
+    result
+}
+
+unsafe fn c_data<'a>(data_ptr: *const c_char, data_len: size_t) -> Result<&'a [u8], LibError> {
+    if data_ptr.is_null() {
+        return Err(LibError::InvalidArgument(String::from("data is null")));
+    }
+
+    Ok(std::slice::from_raw_parts(
+        data_ptr as *const c_uchar,
+        data_len,
+    ))
+}
+
+unsafe fn open_file_path(game: PdsGame, path: *const c_char) -> Result<PdsFile<'static>, LibError> {
+    parse_with(|| PdsFile::from_path(game, c_path(path)?))
+}
+
+unsafe fn parse_file(
+    game: PdsGame,
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> Result<PdsFile<'static>, LibError> {
+    parse_with(|| PdsFile::from_slice(game, c_data(data_ptr, data_len)?))
+}
+
+unsafe fn parse_owned_file(
+    game: PdsGame,
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> Result<PdsFile<'static>, LibError> {
+    parse_with(|| PdsFile::from_owned(game, c_data(data_ptr, data_len)?.to_vec()))
+}
+
+/// Takes the error of the most recent function that failed on the calling
+/// thread, or null if there is no such error. Functions only replace the last
+/// error when they fail, so the last error should be checked immediately after
+/// a function signals failure, for instance when one of the `_or_null`
+/// functions returns null.
+///
+/// The returned error must be freed with `rakaly_free_error`, and a
+/// subsequent call returns null until another function fails.
+#[no_mangle]
+pub extern "C" fn rakaly_last_error() -> *mut PdsError {
+    match take_last_error() {
+        Some(err) => Box::into_raw(Box::new(err)),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Routes diagnostic events, like the detected save kind, the zip entries
+/// that were read, the number of unknown tokens, and how long a melt took,
+/// to the given callback. Events more verbose than the given level are not
+/// formatted or passed to the callback.
+///
+/// The callback receives the given user data, the level of the event, and a
+/// UTF-8 message that is not null terminated and is only valid for the
+/// duration of the call. Passing a null callback or `PdsLogLevel_Off`
+/// disables logging, which is the default.
+///
+/// Returns false and leaves logging unchanged when the level is not a
+/// `PdsLogLevel` value, with the error available from `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - The callback must be safe to call with the given user data from any
+///   thread that calls into librakaly, and may be called concurrently
+/// - The callback must not call `rakaly_set_log_callback`
+/// - The user data must remain valid until the callback is replaced. Replacing
+///   the callback waits for calls in progress to return
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_set_log_callback(
+    level: u32,
+    callback: RakalyLogCallback,
+    user_data: *mut c_void,
+) -> bool {
+    let res = unwind::catch_unwind(|| {
+        let level = PdsLogLevel::try_from(level)?;
+        log::set_logger(level, callback, user_data);
+        Ok(())
+    });
+
+    match res.and_then(|x| x) {
+        Ok(()) => true,
+        Err(err) => {
+            record_last_error(err);
+            false
+        }
+    }
+}
+
+/// Same as `rakaly_file` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// The data is assumed to exist for the duration while the save is in use.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_or_null(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFile<'static> {
+    or_null(parse_file(PdsGame::Unknown, data_ptr, data_len))
+}
+
+/// Same as `rakaly_file_owned` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// The data must be valid for the duration of this function.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_owned_or_null(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFile<'static> {
+    or_null(parse_owned_file(PdsGame::Unknown, data_ptr, data_len))
+}
+
+/// Same as `rakaly_file_open_path` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a null terminated string
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_open_path_or_null(
+    path: *const c_char,
+) -> *mut PdsFile<'static> {
+    or_null(open_file_path(PdsGame::Unknown, path))
+}
+
+/// Same as `rakaly_eu4_file` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// The data is assumed to exist for the duration while the save is in use.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_eu4_file_or_null(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFile<'static> {
+    or_null(parse_file(PdsGame::Eu4, data_ptr, data_len))
+}
+
+/// Same as `rakaly_eu4_file_owned` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// The data must be valid for the duration of this function.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_eu4_file_owned_or_null(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFile<'static> {
+    or_null(parse_owned_file(PdsGame::Eu4, data_ptr, data_len))
+}
+
+/// Same as `rakaly_eu4_file_open_path` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a null terminated string
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_eu4_file_open_path_or_null(
+    path: *const c_char,
+) -> *mut PdsFile<'static> {
+    or_null(open_file_path(PdsGame::Eu4, path))
+}
+
+/// Same as `rakaly_ck3_file` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// The data is assumed to exist for the duration while the save is in use.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_ck3_file_or_null(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFile<'static> {
+    or_null(parse_file(PdsGame::Ck3, data_ptr, data_len))
+}
+
+/// Same as `rakaly_ck3_file_owned` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// The data must be valid for the duration of this function.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_ck3_file_owned_or_null(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFile<'static> {
+    or_null(parse_owned_file(PdsGame::Ck3, data_ptr, data_len))
+}
+
+/// Same as `rakaly_ck3_file_open_path` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a null terminated string
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_ck3_file_open_path_or_null(
+    path: *const c_char,
+) -> *mut PdsFile<'static> {
+    or_null(open_file_path(PdsGame::Ck3, path))
+}
+
+/// Same as `rakaly_imperator_file` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// The data is assumed to exist for the duration while the save is in use.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_imperator_file_or_null(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFile<'static> {
+    or_null(parse_file(PdsGame::Imperator, data_ptr, data_len))
+}
+
+/// Same as `rakaly_imperator_file_owned` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// The data must be valid for the duration of this function.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_imperator_file_owned_or_null(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFile<'static> {
+    or_null(parse_owned_file(PdsGame::Imperator, data_ptr, data_len))
+}
+
+/// Same as `rakaly_imperator_file_open_path` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a null terminated string
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_imperator_file_open_path_or_null(
+    path: *const c_char,
+) -> *mut PdsFile<'static> {
+    or_null(open_file_path(PdsGame::Imperator, path))
+}
+
+/// Same as `rakaly_hoi4_file` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// The data is assumed to exist for the duration while the save is in use.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_hoi4_file_or_null(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFile<'static> {
+    or_null(parse_file(PdsGame::Hoi4, data_ptr, data_len))
+}
+
+/// Same as `rakaly_hoi4_file_owned` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// The data must be valid for the duration of this function.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_hoi4_file_owned_or_null(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFile<'static> {
+    or_null(parse_owned_file(PdsGame::Hoi4, data_ptr, data_len))
+}
+
+/// Same as `rakaly_hoi4_file_open_path` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a null terminated string
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_hoi4_file_open_path_or_null(
+    path: *const c_char,
+) -> *mut PdsFile<'static> {
+    or_null(open_file_path(PdsGame::Hoi4, path))
+}
+
+/// Same as `rakaly_vic3_file` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// The data is assumed to exist for the duration while the save is in use.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_vic3_file_or_null(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFile<'static> {
+    or_null(parse_file(PdsGame::Vic3, data_ptr, data_len))
+}
+
+/// Same as `rakaly_vic3_file_owned` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// The data must be valid for the duration of this function.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_vic3_file_owned_or_null(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFile<'static> {
+    or_null(parse_owned_file(PdsGame::Vic3, data_ptr, data_len))
+}
+
+/// Same as `rakaly_vic3_file_open_path` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a null terminated string
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_vic3_file_open_path_or_null(
+    path: *const c_char,
+) -> *mut PdsFile<'static> {
+    or_null(open_file_path(PdsGame::Vic3, path))
+}
+
+/// Same as `rakaly_eu5_file` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// The data is assumed to exist for the duration while the save is in use.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_eu5_file_or_null(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFile<'static> {
+    or_null(parse_file(PdsGame::Eu5, data_ptr, data_len))
+}
+
+/// Same as `rakaly_eu5_file_owned` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// The data must be valid for the duration of this function.
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_eu5_file_owned_or_null(
+    data_ptr: *const c_char,
+    data_len: size_t,
+) -> *mut PdsFile<'static> {
+    or_null(parse_owned_file(PdsGame::Eu5, data_ptr, data_len))
+}
+
+/// Same as `rakaly_eu5_file_open_path` but returns the save directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a null terminated string
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_eu5_file_open_path_or_null(
+    path: *const c_char,
+) -> *mut PdsFile<'static> {
+    or_null(open_file_path(PdsGame::Eu5, path))
+}
+
+/// Same as `rakaly_file_melt` but returns the melted output directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsFile`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_melt_or_null(ptr: *const PdsFile) -> *mut MeltedBuffer {
+    or_null(file_melt(ptr, std::ptr::null()))
+}
+
+/// Same as `rakaly_file_melt_with_options` but returns the melted output directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsFile`
+/// - Must pass in a valid pointer to `MeltOptions` or null
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_melt_with_options_or_null(
+    ptr: *const PdsFile,
+    options: *const MeltOptions,
+) -> *mut MeltedBuffer {
+    or_null(file_melt(ptr, options))
+}
+
+/// Same as `rakaly_file_meta` but a save without easily extractable metadata
+/// is also an error. Null is returned on failure and the error is available
+/// from `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsFile`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_meta_or_null(
+    ptr: *const PdsFile<'static>,
+) -> *mut PdsMeta<'static> {
+    or_null(file_meta(ptr).and_then(|meta| {
+        meta.ok_or_else(|| {
+            record_last_error(LibError::UnsupportedOperation(String::from(
+                "extracting metadata from a save without it",
+            )))
+        })
+    }))
+}
+
+/// Same as `rakaly_file_meta_melt` but returns the melted output directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsMeta`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_meta_melt_or_null(ptr: *const PdsMeta) -> *mut MeltedBuffer {
+    or_null(meta_melt(ptr, std::ptr::null()))
+}
+
+/// Same as `rakaly_file_meta_melt_with_options` but returns the melted output directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsMeta`
+/// - Must pass in a valid pointer to `MeltOptions` or null
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_meta_melt_with_options_or_null(
+    ptr: *const PdsMeta,
+    options: *const MeltOptions,
+) -> *mut MeltedBuffer {
+    or_null(meta_melt(ptr, options))
+}
+
+/// Same as `rakaly_file_melt_to_writer` but returns the melted output directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsFile`
+/// - Must pass in a valid pointer to `MeltOptions` or null
+/// - The callback must be safe to call with the given user data
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_melt_to_writer_or_null(
+    ptr: *const PdsFile,
+    options: *const MeltOptions,
+    write_cb: RakalyWriteCallback,
+    user_data: *mut c_void,
+) -> *mut MeltedBuffer {
+    or_null(melt_to_writer(ptr, options, write_cb, user_data))
+}
+
+/// Same as `rakaly_file_to_json` but returns the JSON output directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsFile`
+/// - Must pass in a valid pointer to `MeltOptions` or null
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_to_json_or_null(
+    ptr: *const PdsFile,
+    options: *const MeltOptions,
+    pretty: bool,
+    duplicate_keys: u32,
+) -> *mut MeltedBuffer {
+    or_null(file_to_json(ptr, options, pretty, duplicate_keys))
+}
+
+/// Same as `rakaly_meta_to_json` but returns the JSON output directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsMeta`
+/// - Must pass in a valid pointer to `MeltOptions` or null
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_meta_to_json_or_null(
+    ptr: *const PdsMeta,
+    options: *const MeltOptions,
+    pretty: bool,
+    duplicate_keys: u32,
+) -> *mut MeltedBuffer {
+    or_null(meta_to_json(ptr, options, pretty, duplicate_keys))
+}
+
+/// Same as `rakaly_file_summary` but returns the summary directly.
+/// Null is returned on failure and the error is available from
+/// `rakaly_last_error`.
+///
+/// # Safety
+///
+/// - Must pass in a valid pointer to a `PdsFile`
+#[no_mangle]
+pub unsafe extern "C" fn rakaly_file_summary_or_null(ptr: *const PdsFile) -> *mut PdsSummary {
+    or_null(file_summary(ptr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn null_path_is_an_error_result() {
+        let opens: [unsafe extern "C" fn(*const c_char) -> *mut PdsFileResult<'static>; 7] = [
+            rakaly_file_open_path,
+            rakaly_eu4_file_open_path,
+            rakaly_ck3_file_open_path,
+            rakaly_imperator_file_open_path,
+            rakaly_hoi4_file_open_path,
+            rakaly_vic3_file_open_path,
+            rakaly_eu5_file_open_path,
+        ];
+
+        for open in opens.iter() {
+            unsafe {
+                let res = open(std::ptr::null());
+                assert!(!res.is_null());
+                let err = rakaly_file_error(res);
+                assert!(!err.is_null());
+                assert_eq!(rakaly_error_code(err), PdsErrorCode::InvalidArgument);
+                rakaly_free_error(err);
+            }
+        }
+    }
+
+    #[test]
+    fn negative_buffer_lengths_are_rejected() {
+        let mut buffer = [0 as c_char; 16];
+        let err = PdsError::from(&LibError::InvalidArgument(String::from("invalid")));
+        unsafe {
+            assert_eq!(rakaly_error_write_data(&err, buffer.as_mut_ptr(), -1), -1);
+            assert_eq!(
+                rakaly_error_write_key_path(&err, buffer.as_mut_ptr(), -1),
+                -1
+            );
+
+            let game = PdsGame::Eu4 as u32;
+            assert_eq!(rakaly_token_name(game, 0, buffer.as_mut_ptr(), -1), -1);
+            let err = rakaly_last_error();
+            assert_eq!(rakaly_error_code(err), PdsErrorCode::InvalidArgument);
+            rakaly_free_error(err);
+        }
+    }
+
+    #[test]
+    fn null_inputs_set_the_last_error() {
+        fn assert_last_error() {
+            let err = rakaly_last_error();
+            assert!(!err.is_null());
+            unsafe {
+                assert_eq!(rakaly_error_code(err), PdsErrorCode::InvalidArgument);
+                rakaly_free_error(err);
+            }
+        }
+
+        let null = std::ptr::null();
+        unsafe {
+            assert!(rakaly_file_or_null(null, 0).is_null());
+            assert_last_error();
+            assert!(rakaly_eu4_file_owned_or_null(null, 0).is_null());
+            assert_last_error();
+            assert!(rakaly_file_open_path_or_null(null).is_null());
+            assert_last_error();
+            assert!(rakaly_file_melt_or_null(null).is_null());
+            assert_last_error();
+            assert!(rakaly_file_meta(null).is_null());
+            assert_last_error();
+            assert!(rakaly_file_meta_or_null(null).is_null());
+            assert_last_error();
+            assert!(rakaly_file_meta_melt_with_options_or_null(null, null).is_null());
+            assert_last_error();
+            assert!(rakaly_file_to_json_or_null(null, null, false, 0).is_null());
+            assert_last_error();
+            assert!(rakaly_meta_to_json_or_null(null, null, false, 0).is_null());
+            assert_last_error();
+            assert!(rakaly_file_summary_or_null(null).is_null());
+            assert_last_error();
+            assert_eq!(rakaly_token_lookup(PdsGame::Eu4 as u32, null), -1);
+            assert_last_error();
+            let invalid = b"\xff\0";
+            assert_eq!(
+                rakaly_token_lookup(PdsGame::Eu4 as u32, invalid.as_ptr() as *const c_char),
+                -1
+            );
+            assert_last_error();
+
+            let res = rakaly_file_melt(null);
+            let err = rakaly_melt_error(res);
+            assert_eq!(rakaly_error_code(err), PdsErrorCode::InvalidArgument);
+            rakaly_free_error(err);
+        }
+        assert_last_error();
+        assert!(rakaly_last_error().is_null());
+    }
+
+    #[test]
+    fn failed_melt_reports_the_key_path() {
+        const EQUAL: [u8; 2] = [0x01, 0x00];
+        const OPEN: [u8; 2] = [0x03, 0x00];
+        const CLOSE: [u8; 2] = [0x04, 0x00];
+        const U32: [u8; 2] = [0x14, 0x00];
+
+        let tokens = test_utils::load_tokens(PdsGame::Hoi4, b"0xfff1 player\n0xfff2 countries");
+
+        // Keep the magic and the player field
+        let mut data = test_utils::hoi4_binary_save(0xfff1, 0xfff0);
+        data.truncate(18);
+        data.extend_from_slice(&0xfff2u16.to_le_bytes());
+        data.extend_from_slice(&EQUAL);
+        data.extend_from_slice(&OPEN);
+        data.extend_from_slice(&0xfff0u16.to_le_bytes());
+        data.extend_from_slice(&EQUAL);
+        data.extend_from_slice(&U32);
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&CLOSE);
+
+        let mut options = MeltOptions::default();
+        options.set_on_failed_resolve(PdsFailedResolve::Error);
+        let err = PdsFile::from_slice(PdsGame::Hoi4, &data)
+            .and_then(|file| file.melt_file(&options))
+            .map(|_| ())
+            .unwrap_err();
+        drop(tokens);
+
+        let err = PdsError::from(&err);
+        assert_eq!(err.code(), PdsErrorCode::UnknownToken);
+        unsafe {
+            assert_eq!(rakaly_error_key_path_length(&err), 9);
+            let mut buffer = [0 as c_char; 9];
+            assert_eq!(rakaly_error_write_key_path(&err, buffer.as_mut_ptr(), 9), 9);
+            assert_eq!(&*(buffer.as_ptr() as *const [u8; 9]), b"countries");
+        }
+    }
+
+    #[test]
+    fn parse_errors_report_their_offset() {
+        let err = LibError::from(jomini::TextTape::from_slice(b"a=b\n}").unwrap_err());
+        let err = PdsError::from(&err);
+        unsafe {
+            assert!(rakaly_error_offset(&err) >= 0);
+            assert_eq!(rakaly_error_key_path_length(&err), 0);
+            assert_eq!(rakaly_error_offset(std::ptr::null()), -1);
+        }
+    }
+
+    #[test]
+    fn failed_melt_to_path_keeps_existing_file() {
+        let dir = std::env::temp_dir().join(format!("rakaly-melt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dest = dir.join("melted.txt");
+        std::fs::write(&dest, "previous").unwrap();
+        let path = std::ffi::CString::new(dest.to_str().unwrap()).unwrap();
+
+        let err = unsafe {
+            melt_to_path(path.as_ptr(), || {
+                Ok(|out: &mut BufWriter<File>| {
+                    std::io::Write::write_all(out, b"partial")?;
+                    Err(LibError::UnknownGame)
+                })
+            })
+        };
+        assert!(!err.is_null());
+        unsafe { rakaly_free_error(err) };
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "previous");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        let err = unsafe {
+            melt_to_path(path.as_ptr(), || {
+                Ok(|out: &mut BufWriter<File>| {
+                    std::io::Write::write_all(out, b"melted")?;
+                    Ok(MeltOutcome::Text)
+                })
+            })
+        };
+        assert!(err.is_null());
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "melted");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn null_melt_to_path_arguments_are_errors() {
+        let path = std::ffi::CString::new("unused.txt").unwrap();
+        unsafe {
+            let err = rakaly_file_melt_to_path(std::ptr::null(), std::ptr::null(), path.as_ptr());
+            assert_eq!(rakaly_error_code(err), PdsErrorCode::InvalidArgument);
+            rakaly_free_error(err);
+
+            let err =
+                rakaly_file_meta_melt_to_path(std::ptr::null(), std::ptr::null(), std::ptr::null());
+            assert_eq!(rakaly_error_code(err), PdsErrorCode::InvalidArgument);
+            rakaly_free_error(err);
+        }
+        assert!(!Path::new("unused.txt").exists());
     }
 }
@@ -0,0 +1,155 @@
+//! Routes diagnostic events to a logging callback provided by the host
+//! application.
+
+use crate::errors::LibError;
+use libc::{c_char, c_void, size_t};
+use std::{
+    convert::TryFrom,
+    fmt,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        PoisonError, RwLock,
+    },
+};
+
+/// The severity of a log event
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PdsLogLevel {
+    /// Disables logging when used as the maximum level
+    Off = 0,
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+}
+
+impl TryFrom<u32> for PdsLogLevel {
+    type Error = LibError;
+
+    fn try_from(value: u32) -> Result<Self, LibError> {
+        match value {
+            0 => Ok(PdsLogLevel::Off),
+            1 => Ok(PdsLogLevel::Error),
+            2 => Ok(PdsLogLevel::Warn),
+            3 => Ok(PdsLogLevel::Info),
+            4 => Ok(PdsLogLevel::Debug),
+            _ => Err(LibError::invalid_enum("PdsLogLevel", value)),
+        }
+    }
+}
+
+/// A function that receives log events. The message is UTF-8 and is not null
+/// terminated.
+pub type RakalyLogCallback = Option<
+    unsafe extern "C" fn(
+        user_data: *mut c_void,
+        level: PdsLogLevel,
+        msg: *const c_char,
+        len: size_t,
+    ),
+>;
+
+struct Logger {
+    callback: unsafe extern "C" fn(*mut c_void, PdsLogLevel, *const c_char, size_t),
+    user_data: *mut c_void,
+}
+
+// The host is responsible for the callback being safe to call from any thread
+unsafe impl Send for Logger {}
+unsafe impl Sync for Logger {}
+
+/// The maximum level that is logged, checked before formatting an event
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(PdsLogLevel::Off as u8);
+static LOGGER: RwLock<Option<Logger>> = RwLock::new(None);
+
+/// Sets the callback that receives events at or above the given level. A
+/// missing callback disables logging.
+pub fn set_logger(level: PdsLogLevel, callback: RakalyLogCallback, user_data: *mut c_void) {
+    let mut logger = LOGGER.write().unwrap_or_else(PoisonError::into_inner);
+    *logger = callback.map(|callback| Logger {
+        callback,
+        user_data,
+    });
+
+    let level = if logger.is_some() {
+        level
+    } else {
+        PdsLogLevel::Off
+    };
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: PdsLogLevel) -> bool {
+    level != PdsLogLevel::Off && level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn log(level: PdsLogLevel, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+
+    // The lock is held during the call so that replacing the logger waits
+    // for in-flight calls to finish with the user data
+    let logger = LOGGER.read().unwrap_or_else(PoisonError::into_inner);
+    if let Some(logger) = logger.as_ref() {
+        let msg = fmt::format(args);
+        unsafe {
+            (logger.callback)(
+                logger.user_data,
+                level,
+                msg.as_ptr() as *const c_char,
+                msg.len(),
+            )
+        };
+    }
+}
+
+pub fn warn(args: fmt::Arguments) {
+    log(PdsLogLevel::Warn, args)
+}
+
+pub fn info(args: fmt::Arguments) {
+    log(PdsLogLevel::Info, args)
+}
+
+pub fn debug(args: fmt::Arguments) {
+    log(PdsLogLevel::Debug, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Tests share the global logger, so they are run one at a time
+    static LOGGER_TEST: Mutex<()> = Mutex::new(());
+
+    unsafe extern "C" fn record(
+        msgs: *mut c_void,
+        _: PdsLogLevel,
+        msg: *const c_char,
+        len: size_t,
+    ) {
+        let msg = std::slice::from_raw_parts(msg as *const u8, len);
+        let msg = String::from_utf8_lossy(msg).into_owned();
+        let msgs = &*(msgs as *const Mutex<Vec<String>>);
+        msgs.lock().unwrap().push(msg);
+    }
+
+    #[test]
+    fn events_above_the_level_are_not_logged() {
+        let _guard = LOGGER_TEST.lock().unwrap_or_else(PoisonError::into_inner);
+        let msgs: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let user_data = &msgs as *const Mutex<Vec<String>> as *mut c_void;
+        set_logger(PdsLogLevel::Info, Some(record), user_data);
+        warn(format_args!("test warn event"));
+        debug(format_args!("test debug event"));
+        set_logger(PdsLogLevel::Off, None, std::ptr::null_mut());
+
+        let msgs = msgs.into_inner().unwrap();
+        assert!(msgs.iter().any(|x| x == "test warn event"));
+        assert!(msgs.iter().all(|x| x != "test debug event"));
+        assert!(!enabled(PdsLogLevel::Error));
+    }
+}
@@ -1,10 +1,16 @@
 use crate::{
+    diagnostics::TokenContext,
     errors::LibError,
+    file::PdsGame,
+    log,
+    options::MeltOptions,
     tokens::{eu4_tokens_resolver, hoi4_tokens_resolver},
+    tracker::MeltTracker,
 };
 use eu4save::file::Eu4SliceFile;
 use hoi4save::file::Hoi4SliceFile;
-use std::io::Cursor;
+use libc::{c_char, c_int, c_void, size_t};
+use std::{collections::HashMap, io::Write, time::Duration};
 
 pub enum MeltedBufferResult {
     Ok(MeltedBuffer),
@@ -14,65 +20,433 @@ pub enum MeltedBufferResult {
 /// An opaque struct that holds the results of the melting operatation
 pub enum MeltedBuffer {
     Verbatim,
-    Text { header: Vec<u8>, body: Vec<u8> },
-    Binary { body: Vec<u8>, unknown_tokens: bool },
+    Text {
+        header: Vec<u8>,
+        body: Vec<u8>,
+    },
+    Binary {
+        body: Vec<u8>,
+        unknown_tokens: UnknownTokens,
+    },
+
+    /// The melted output was written to a caller provided destination
+    Streamed {
+        unknown_tokens: UnknownTokens,
+    },
+}
+
+/// A binary token that could not be resolved while melting
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PdsUnknownToken {
+    /// The binary token id
+    pub id: u16,
+
+    /// The number of times the melter encountered the token, including where
+    /// the `PdsFailedResolve_Ignore` strategy left the token out
+    pub occurrences: u64,
+}
+
+/// The unknown tokens encountered while melting, sorted by id
+#[derive(Debug, Clone, Default)]
+pub struct UnknownTokens {
+    tokens: Vec<PdsUnknownToken>,
+
+    /// The context of each token, only kept when diagnostics are enabled
+    contexts: Vec<TokenContext>,
+}
+
+impl UnknownTokens {
+    pub fn new(counts: Vec<(u16, u64)>, mut contexts: HashMap<u16, TokenContext>) -> Self {
+        let mut tokens: Vec<_> = counts
+            .into_iter()
+            .map(|(id, occurrences)| PdsUnknownToken { id, occurrences })
+            .collect();
+        tokens.sort_unstable_by_key(|x| x.id);
+        let contexts = tokens
+            .iter()
+            .map(|x| contexts.remove(&x.id).unwrap_or_default())
+            .collect();
+        UnknownTokens { tokens, contexts }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn tokens(&self) -> &[PdsUnknownToken] {
+        &self.tokens
+    }
+
+    pub fn context(&self, index: usize) -> Option<&TokenContext> {
+        self.contexts.get(index)
+    }
+}
+
+/// Describes the melted output that was written to a writer
+pub enum MeltOutcome {
+    /// The input is already plaintext and nothing was written
+    Verbatim,
+    Text,
+    Binary {
+        unknown_tokens: UnknownTokens,
+    },
+}
+
+impl MeltOutcome {
+    /// Drops the context of unknown tokens unless diagnostics are enabled
+    pub fn diagnose(mut self, options: &MeltOptions) -> Self {
+        if let MeltOutcome::Binary { unknown_tokens } = &mut self {
+            if !options.diagnostics() {
+                unknown_tokens.contexts = Vec::new();
+            }
+        }
+        self
+    }
+
+    pub fn into_buffer(self, body: Vec<u8>) -> MeltedBuffer {
+        match self {
+            MeltOutcome::Verbatim => MeltedBuffer::Verbatim,
+            MeltOutcome::Text => MeltedBuffer::Text {
+                header: Vec::new(),
+                body,
+            },
+            MeltOutcome::Binary { unknown_tokens } => MeltedBuffer::Binary {
+                body,
+                unknown_tokens,
+            },
+        }
+    }
+
+    pub fn into_streamed(self) -> MeltedBuffer {
+        match self {
+            MeltOutcome::Verbatim | MeltOutcome::Text => MeltedBuffer::Streamed {
+                unknown_tokens: UnknownTokens::default(),
+            },
+            MeltOutcome::Binary { unknown_tokens } => MeltedBuffer::Streamed { unknown_tokens },
+        }
+    }
 }
 
 impl MeltedBuffer {
+    pub fn unknown_tokens(&self) -> Option<&UnknownTokens> {
+        match self {
+            MeltedBuffer::Binary { unknown_tokens, .. }
+            | MeltedBuffer::Streamed { unknown_tokens } => Some(unknown_tokens),
+            MeltedBuffer::Verbatim | MeltedBuffer::Text { .. } => None,
+        }
+    }
+
     pub fn len(&self) -> usize {
         match self {
-            MeltedBuffer::Verbatim => 0,
+            MeltedBuffer::Verbatim | MeltedBuffer::Streamed { .. } => 0,
             MeltedBuffer::Text { header, body } => header.len() + body.len(),
             MeltedBuffer::Binary { body, .. } => body.len(),
         }
     }
+
+    /// The non-empty segments that make up the melted data, in order
+    pub fn segments(&self) -> impl Iterator<Item = &[u8]> {
+        let (header, body): (&[u8], &[u8]) = match self {
+            MeltedBuffer::Verbatim | MeltedBuffer::Streamed { .. } => (&[], &[]),
+            MeltedBuffer::Text { header, body } => (header, body),
+            MeltedBuffer::Binary { body, .. } => (&[], body),
+        };
+
+        std::iter::once(header)
+            .chain(std::iter::once(body))
+            .filter(|x| !x.is_empty())
+    }
+}
+
+/// Logs how long a melt took and the unknown tokens it encountered
+pub fn log_melt(game: PdsGame, what: &str, outcome: &MeltOutcome, elapsed: Duration) {
+    match outcome {
+        MeltOutcome::Verbatim => log::debug(format_args!(
+            "{} {} is already plaintext and was not melted",
+            game, what
+        )),
+        MeltOutcome::Text => log::info(format_args!("melted {} {} in {:?}", game, what, elapsed)),
+        MeltOutcome::Binary { unknown_tokens } => {
+            log::info(format_args!("melted {} {} in {:?}", game, what, elapsed));
+            if !unknown_tokens.is_empty() {
+                let occurrences: u64 = unknown_tokens.tokens().iter().map(|x| x.occurrences).sum();
+                log::warn(format_args!(
+                    "{} {} contained {} unknown tokens ({} occurrences)",
+                    game,
+                    what,
+                    unknown_tokens.tokens().len(),
+                    occurrences
+                ));
+            }
+        }
+    }
 }
 
 pub trait Melter {
-    fn melt(self) -> Result<MeltedBuffer, LibError>;
+    fn melt<W: Write>(self, options: &MeltOptions, output: W) -> Result<MeltOutcome, LibError>;
 }
 
 impl Melter for &'_ Eu4SliceFile<'_> {
-    fn melt(self) -> Result<MeltedBuffer, LibError> {
+    fn melt<W: Write>(self, options: &MeltOptions, mut out: W) -> Result<MeltOutcome, LibError> {
         if matches!(self.encoding(), eu4save::Encoding::Text) {
-            return Ok(MeltedBuffer::Verbatim);
+            return Ok(MeltOutcome::Verbatim);
+        }
+
+        if let eu4save::file::Eu4SliceFileKind::Zip(_) = self.kind() {
+            log::debug(format_args!(
+                "reading the meta, gamestate, and ai entries from the eu4 zip"
+            ));
         }
 
-        let mut out = Cursor::new(Vec::new());
-        let options = eu4save::MeltOptions::new()
-            .verbatim(true)
-            .on_failed_resolve(eu4save::FailedResolveStrategy::Stringify);
-        let doc = self.melt(options, eu4_tokens_resolver(), &mut out)?;
+        let mut tracker = MeltTracker::new(&mut out, options);
+        let options = options.eu4();
+        let resolver = tracker.resolver(eu4_tokens_resolver()?);
+        let doc = self.melt(options, resolver, &mut tracker);
+        let doc = tracker.with_key_path(doc)?;
 
         if self.encoding().is_text() {
-            Ok(MeltedBuffer::Text {
-                header: Vec::new(),
-                body: out.into_inner(),
-            })
+            Ok(MeltOutcome::Text)
         } else {
-            Ok(MeltedBuffer::Binary {
-                body: out.into_inner(),
-                unknown_tokens: !doc.unknown_tokens().is_empty(),
+            Ok(MeltOutcome::Binary {
+                unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
             })
         }
     }
 }
 
 impl Melter for &'_ Hoi4SliceFile<'_> {
-    fn melt(self) -> Result<MeltedBuffer, LibError> {
-        let mut out = Cursor::new(Vec::new());
+    fn melt<W: Write>(self, options: &MeltOptions, mut out: W) -> Result<MeltOutcome, LibError> {
         if matches!(self.encoding(), hoi4save::Encoding::Plaintext) {
-            return Ok(MeltedBuffer::Verbatim);
+            return Ok(MeltOutcome::Verbatim);
         }
 
-        let options = hoi4save::MeltOptions::new()
-            .verbatim(true)
-            .on_failed_resolve(hoi4save::FailedResolveStrategy::Stringify);
-        let doc = self.melt(options, hoi4_tokens_resolver(), &mut out)?;
+        let mut tracker = MeltTracker::new(&mut out, options);
+        let options = options.hoi4();
+        let resolver = tracker.resolver(hoi4_tokens_resolver()?);
+        let doc = self.melt(options, resolver, &mut tracker);
+        let doc = tracker.with_key_path(doc)?;
 
-        Ok(MeltedBuffer::Binary {
-            body: out.into_inner(),
-            unknown_tokens: !doc.unknown_tokens().is_empty(),
+        Ok(MeltOutcome::Binary {
+            unknown_tokens: tracker.unknown_tokens(doc.unknown_tokens()),
         })
     }
 }
+
+/// Removes the fields that flag a save as ironman from melted output when
+/// active. The melters write each field with a scalar value on its own line,
+/// so the output is filtered by line.
+pub struct IronmanFilter<W> {
+    inner: W,
+    active: bool,
+
+    /// The start of a line that was cut off by the end of a write
+    line: Vec<u8>,
+}
+
+impl<W: Write> IronmanFilter<W> {
+    pub fn new(inner: W, active: bool) -> Self {
+        IronmanFilter {
+            inner,
+            active,
+            line: Vec::new(),
+        }
+    }
+
+    /// Writes the last line, which may not end with a newline
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        let line = std::mem::take(&mut self.line);
+        if !is_ironman_field(&line) {
+            self.inner.write_all(&line)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for IronmanFilter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if !self.active {
+            return self.inner.write(buf);
+        }
+
+        let mut pos = 0;
+        if !self.line.is_empty() {
+            let Some(end) = buf.iter().position(|&x| x == b'\n') else {
+                self.line.extend_from_slice(buf);
+                return Ok(buf.len());
+            };
+
+            pos = end + 1;
+            self.line.extend_from_slice(&buf[..pos]);
+            let line = std::mem::take(&mut self.line);
+            if !is_ironman_field(&line) {
+                self.inner.write_all(&line)?;
+            }
+        }
+
+        // Lines are written in runs that end before each ironman field
+        let mut start = pos;
+        while let Some(end) = buf[pos..].iter().position(|&x| x == b'\n') {
+            let line_end = pos + end + 1;
+            if is_ironman_field(&buf[pos..line_end]) {
+                self.inner.write_all(&buf[start..pos])?;
+                start = line_end;
+            }
+            pos = line_end;
+        }
+
+        self.inner.write_all(&buf[start..pos])?;
+        self.line.extend_from_slice(&buf[pos..]);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn is_ironman_field(line: &[u8]) -> bool {
+    let line = line.trim_ascii();
+    let value = line
+        .strip_prefix(b"ironman=")
+        .or_else(|| line.strip_prefix(b"is_ironman="));
+    value.is_some_and(|x| !x.is_empty() && !x.contains(&b'{'))
+}
+
+/// Forwards written data to a caller provided C function. Once the function
+/// reports a failure, the writer is poisoned and the function is not called
+/// again.
+pub struct CallbackWriter {
+    write_cb: unsafe extern "C" fn(*mut c_void, *const c_char, size_t) -> c_int,
+    user_data: *mut c_void,
+    poisoned: bool,
+}
+
+impl CallbackWriter {
+    pub fn new(
+        write_cb: unsafe extern "C" fn(*mut c_void, *const c_char, size_t) -> c_int,
+        user_data: *mut c_void,
+    ) -> Self {
+        CallbackWriter {
+            write_cb,
+            user_data,
+            poisoned: false,
+        }
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+}
+
+impl Write for CallbackWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.poisoned {
+            return Err(std::io::Error::other("write callback previously failed"));
+        }
+
+        let code =
+            unsafe { (self.write_cb)(self.user_data, buf.as_ptr() as *const c_char, buf.len()) };
+        if code != 0 {
+            self.poisoned = true;
+            return Err(std::io::Error::other(format!(
+                "write callback returned {}",
+                code
+            )));
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{file::PdsFile, options::PdsFailedResolve, test_utils};
+    use std::io::BufWriter;
+
+    unsafe extern "C" fn failing_write(calls: *mut c_void, _: *const c_char, _: size_t) -> c_int {
+        *(calls as *mut usize) += 1;
+        1
+    }
+
+    #[test]
+    fn ironman_fields_are_filtered_by_line() {
+        let data = b"EU4txt\nironman=yes\ndate=1444.11.11\nmeta_data={\n\tironman=yes\n\t\
+            name=\"x\"\n}\nironman_manager={\n}\nis_ironman=yes";
+        let expected = b"EU4txt\ndate=1444.11.11\nmeta_data={\n\tname=\"x\"\n}\n\
+            ironman_manager={\n}\n";
+
+        for size in [1, 3, data.len()] {
+            let mut output = Vec::new();
+            let mut filter = IronmanFilter::new(&mut output, true);
+            for chunk in data.chunks(size) {
+                filter.write_all(chunk).unwrap();
+            }
+            filter.finish().unwrap();
+            assert_eq!(output, expected.to_vec(), "{}", size);
+        }
+
+        let mut output = Vec::new();
+        let mut filter = IronmanFilter::new(&mut output, false);
+        filter.write_all(data).unwrap();
+        filter.finish().unwrap();
+        assert_eq!(output, data.to_vec());
+    }
+
+    #[test]
+    fn failed_callback_is_not_called_again() {
+        let mut calls = 0usize;
+        let writer = CallbackWriter::new(failing_write, &mut calls as *mut usize as *mut c_void);
+        let mut output = BufWriter::with_capacity(4, writer);
+        assert!(output.write_all(b"melted output").is_err());
+        assert!(output.write_all(b"more").is_err());
+
+        let (writer, _) = output.into_parts();
+        assert!(writer.is_poisoned());
+        assert_eq!(calls, 1);
+    }
+
+    fn melt_hoi4(options: &MeltOptions) -> MeltedBuffer {
+        let _tokens = test_utils::load_tokens(PdsGame::Hoi4, b"0xfff1 player");
+        let data = test_utils::hoi4_binary_save(0xfff1, 0xfff0);
+        PdsFile::from_slice(PdsGame::Hoi4, &data)
+            .and_then(|file| file.melt_file(options))
+            .unwrap()
+    }
+
+    #[test]
+    fn counts_unknown_tokens_as_they_are_encountered() {
+        let melted = melt_hoi4(&MeltOptions::default());
+        let body: Vec<u8> = melted.segments().flatten().copied().collect();
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.contains("player=\"FRA\""), "{}", body);
+        assert!(body.contains("__unknown_0xfff0=1"), "{}", body);
+
+        let expected = [PdsUnknownToken {
+            id: 0xfff0,
+            occurrences: 2,
+        }];
+        assert_eq!(melted.unknown_tokens().unwrap().tokens(), &expected);
+    }
+
+    #[test]
+    fn counts_unknown_tokens_that_are_left_out() {
+        let mut options = MeltOptions::default();
+        options.set_on_failed_resolve(PdsFailedResolve::Ignore);
+        let melted = melt_hoi4(&options);
+        let body: Vec<u8> = melted.segments().flatten().copied().collect();
+        let body = String::from_utf8(body).unwrap();
+        assert!(!body.contains("__unknown_0xfff0"), "{}", body);
+
+        let expected = [PdsUnknownToken {
+            id: 0xfff0,
+            occurrences: 2,
+        }];
+        assert_eq!(melted.unknown_tokens().unwrap().tokens(), &expected);
+    }
+}
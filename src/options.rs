@@ -0,0 +1,191 @@
+use crate::errors::LibError;
+use std::convert::TryFrom;
+
+/// How the melter should handle binary tokens that can't be resolved to text
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdsFailedResolve {
+    /// Write the unknown token as a hex string (eg: `__unknown_0x1234`)
+    Stringify = 0,
+
+    /// Skip the field that contains the unknown token
+    Ignore = 1,
+
+    /// Abort the melt with an error
+    Error = 2,
+}
+
+impl TryFrom<u32> for PdsFailedResolve {
+    type Error = LibError;
+
+    fn try_from(value: u32) -> Result<Self, LibError> {
+        match value {
+            0 => Ok(PdsFailedResolve::Stringify),
+            1 => Ok(PdsFailedResolve::Ignore),
+            2 => Ok(PdsFailedResolve::Error),
+            _ => Err(LibError::invalid_enum("PdsFailedResolve", value)),
+        }
+    }
+}
+
+/// An opaque struct that configures how saves are melted
+#[derive(Debug, Clone)]
+pub struct MeltOptions {
+    verbatim: bool,
+    keep_ironman: bool,
+    on_failed_resolve: PdsFailedResolve,
+    diagnostics: bool,
+}
+
+impl Default for MeltOptions {
+    fn default() -> Self {
+        MeltOptions {
+            verbatim: true,
+            keep_ironman: false,
+            on_failed_resolve: PdsFailedResolve::Stringify,
+            diagnostics: false,
+        }
+    }
+}
+
+impl MeltOptions {
+    pub fn set_verbatim(&mut self, verbatim: bool) {
+        self.verbatim = verbatim;
+    }
+
+    pub fn set_keep_ironman(&mut self, keep_ironman: bool) {
+        self.keep_ironman = keep_ironman;
+    }
+
+    pub fn set_on_failed_resolve(&mut self, strategy: PdsFailedResolve) {
+        self.on_failed_resolve = strategy;
+    }
+
+    pub fn set_diagnostics(&mut self, diagnostics: bool) {
+        self.diagnostics = diagnostics;
+    }
+
+    pub fn diagnostics(&self) -> bool {
+        self.diagnostics
+    }
+
+    /// Rejects combinations of options that can't be honored
+    pub fn validate(&self) -> Result<(), LibError> {
+        if self.diagnostics && self.on_failed_resolve != PdsFailedResolve::Stringify {
+            return Err(LibError::InvalidArgument(String::from(
+                "diagnostics require the PdsFailedResolve_Stringify strategy",
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// The game melters are always run verbatim and the ironman flag is
+    /// removed from their output instead, so that the flag can be kept
+    /// without keeping everything else verbatim
+    pub fn strip_ironman(&self) -> bool {
+        !self.verbatim && !self.keep_ironman
+    }
+
+    pub fn eu4(&self) -> eu4save::MeltOptions {
+        let strategy = match self.on_failed_resolve {
+            PdsFailedResolve::Stringify => eu4save::FailedResolveStrategy::Stringify,
+            PdsFailedResolve::Ignore => eu4save::FailedResolveStrategy::Ignore,
+            PdsFailedResolve::Error => eu4save::FailedResolveStrategy::Error,
+        };
+
+        eu4save::MeltOptions::new()
+            .verbatim(true)
+            .on_failed_resolve(strategy)
+    }
+
+    pub fn ck3(&self) -> ck3save::MeltOptions {
+        let strategy = match self.on_failed_resolve {
+            PdsFailedResolve::Stringify => ck3save::FailedResolveStrategy::Stringify,
+            PdsFailedResolve::Ignore => ck3save::FailedResolveStrategy::Ignore,
+            PdsFailedResolve::Error => ck3save::FailedResolveStrategy::Error,
+        };
+
+        ck3save::MeltOptions::new()
+            .verbatim(true)
+            .on_failed_resolve(strategy)
+    }
+
+    pub fn imperator(&self) -> imperator_save::MeltOptions {
+        let strategy = match self.on_failed_resolve {
+            PdsFailedResolve::Stringify => imperator_save::FailedResolveStrategy::Stringify,
+            PdsFailedResolve::Ignore => imperator_save::FailedResolveStrategy::Ignore,
+            PdsFailedResolve::Error => imperator_save::FailedResolveStrategy::Error,
+        };
+
+        imperator_save::MeltOptions::new()
+            .verbatim(true)
+            .on_failed_resolve(strategy)
+    }
+
+    pub fn hoi4(&self) -> hoi4save::MeltOptions {
+        let strategy = match self.on_failed_resolve {
+            PdsFailedResolve::Stringify => hoi4save::FailedResolveStrategy::Stringify,
+            PdsFailedResolve::Ignore => hoi4save::FailedResolveStrategy::Ignore,
+            PdsFailedResolve::Error => hoi4save::FailedResolveStrategy::Error,
+        };
+
+        hoi4save::MeltOptions::new()
+            .verbatim(true)
+            .on_failed_resolve(strategy)
+    }
+
+    pub fn vic3(&self) -> vic3save::MeltOptions {
+        let strategy = match self.on_failed_resolve {
+            PdsFailedResolve::Stringify => vic3save::FailedResolveStrategy::Stringify,
+            PdsFailedResolve::Ignore => vic3save::FailedResolveStrategy::Ignore,
+            PdsFailedResolve::Error => vic3save::FailedResolveStrategy::Error,
+        };
+
+        vic3save::MeltOptions::new()
+            .verbatim(true)
+            .on_failed_resolve(strategy)
+    }
+
+    pub fn eu5(&self) -> eu5save::MeltOptions {
+        let strategy = match self.on_failed_resolve {
+            PdsFailedResolve::Stringify => eu5save::FailedResolveStrategy::Stringify,
+            PdsFailedResolve::Ignore => eu5save::FailedResolveStrategy::Ignore,
+            PdsFailedResolve::Error => eu5save::FailedResolveStrategy::Error,
+        };
+
+        eu5save::MeltOptions::new()
+            .verbatim(true)
+            .on_failed_resolve(strategy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ironman_is_kept_on_its_own() {
+        let mut options = MeltOptions::default();
+        assert!(!options.strip_ironman());
+
+        options.set_verbatim(false);
+        assert!(options.strip_ironman());
+
+        options.set_keep_ironman(true);
+        assert!(!options.strip_ironman());
+    }
+
+    #[test]
+    fn diagnostics_require_stringify() {
+        let mut options = MeltOptions::default();
+        options.set_diagnostics(true);
+        assert!(options.validate().is_ok());
+
+        options.set_on_failed_resolve(PdsFailedResolve::Ignore);
+        assert!(matches!(
+            options.validate(),
+            Err(LibError::InvalidArgument(_))
+        ));
+    }
+}
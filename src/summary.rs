@@ -0,0 +1,238 @@
+use crate::{errors::LibError, file::PdsGame, json::strip_header};
+use jomini::{
+    common::PdsDate,
+    text::de::{from_utf8_slice, from_windows1252_slice},
+};
+use std::convert::TryFrom;
+
+pub enum PdsSummaryResult {
+    Ok(PdsSummary),
+    Err(LibError),
+}
+
+/// A text field of a save summary
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdsSummaryField {
+    /// The version of the game that wrote the save
+    Version = 0,
+
+    /// The in-game date
+    Date = 1,
+
+    /// The player's country tag, character, or name
+    Player = 2,
+
+    /// The name the save was given
+    SaveName = 3,
+}
+
+impl TryFrom<u32> for PdsSummaryField {
+    type Error = LibError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(PdsSummaryField::Version),
+            1 => Ok(PdsSummaryField::Date),
+            2 => Ok(PdsSummaryField::Player),
+            3 => Ok(PdsSummaryField::SaveName),
+            _ => Err(LibError::invalid_enum("PdsSummaryField", value)),
+        }
+    }
+}
+
+/// An opaque struct that holds commonly needed fields from a save's metadata
+#[derive(Debug, Clone)]
+pub struct PdsSummary {
+    game: PdsGame,
+    version: Option<String>,
+    date: Option<String>,
+    player: Option<String>,
+    save_name: Option<String>,
+    ironman: bool,
+    multiplayer: bool,
+}
+
+/// Formats a date as the games write it, like `1444.11.11`
+fn game_date<D: PdsDate>(date: &D) -> String {
+    date.game_fmt().to_string()
+}
+
+impl PdsSummary {
+    /// Builds a summary from the melted plaintext metadata of a game, which is
+    /// deserialized into the metadata model of the game's crate
+    pub fn from_melted(game: PdsGame, data: &[u8]) -> Result<Self, LibError> {
+        let data = strip_header(data);
+        let summary = match game {
+            PdsGame::Eu4 => {
+                let meta: eu4save::models::Meta = from_windows1252_slice(data)?;
+                let version = &meta.savegame_version;
+                let version = format!(
+                    "{}.{}.{}.{}",
+                    version.first, version.second, version.third, version.fourth
+                );
+                PdsSummary {
+                    game,
+                    version: Some(version),
+                    date: Some(game_date(&meta.date)),
+                    player: Some(meta.player.to_string()),
+                    save_name: Some(meta.save_game),
+                    ironman: meta.ironman,
+                    multiplayer: meta.multi_player,
+                }
+            }
+            PdsGame::Ck3 => {
+                let header: ck3save::models::Header = from_utf8_slice(data)?;
+                let meta = header.meta_data;
+                PdsSummary {
+                    game,
+                    version: Some(meta.version),
+                    date: Some(game_date(&meta.meta_date)),
+                    player: Some(meta.meta_player_name),
+                    save_name: meta.meta_title_name,
+                    ironman: meta.ironman,
+                    multiplayer: meta.multiplayer,
+                }
+            }
+            PdsGame::Imperator => {
+                let meta: imperator_save::models::Metadata = from_utf8_slice(data)?;
+                PdsSummary {
+                    game,
+                    version: Some(meta.version),
+                    date: Some(game_date(&meta.date)),
+                    player: Some(meta.meta_player_name),
+                    save_name: meta.meta_title_name,
+                    ironman: meta.ironman,
+                    multiplayer: meta.multiplayer,
+                }
+            }
+            PdsGame::Hoi4 => {
+                let meta: hoi4save::models::Hoi4Save = from_utf8_slice(data)?;
+                PdsSummary {
+                    game,
+                    version: Some(meta.version),
+                    date: Some(game_date(&meta.date)),
+                    player: Some(meta.player),
+                    save_name: None,
+                    ironman: meta.ironman,
+                    multiplayer: false,
+                }
+            }
+            PdsGame::Vic3 => {
+                let header: vic3save::models::Header = from_utf8_slice(data)?;
+                let meta = header.meta_data;
+                PdsSummary {
+                    game,
+                    version: Some(meta.version),
+                    date: Some(game_date(&meta.game_date)),
+                    player: meta.player_country_name,
+                    save_name: meta.save_name,
+                    ironman: meta.ironman,
+                    multiplayer: meta.multiplayer,
+                }
+            }
+            PdsGame::Eu5 => {
+                let header: eu5save::models::Header = from_utf8_slice(data)?;
+                let meta = header.metadata;
+                PdsSummary {
+                    game,
+                    version: Some(meta.version),
+                    date: Some(game_date(&meta.date)),
+                    player: meta.player_country_name,
+                    save_name: meta.playthrough_name,
+                    ironman: meta.ironman,
+                    multiplayer: meta.multiplayer,
+                }
+            }
+            PdsGame::Unknown => PdsSummary {
+                game,
+                version: None,
+                date: None,
+                player: None,
+                save_name: None,
+                ironman: false,
+                multiplayer: false,
+            },
+        };
+
+        Ok(summary)
+    }
+
+    pub fn game(&self) -> PdsGame {
+        self.game
+    }
+
+    pub fn field(&self, field: PdsSummaryField) -> Option<&str> {
+        match field {
+            PdsSummaryField::Version => self.version.as_deref(),
+            PdsSummaryField::Date => self.date.as_deref(),
+            PdsSummaryField::Player => self.player.as_deref(),
+            PdsSummaryField::SaveName => self.save_name.as_deref(),
+        }
+    }
+
+    pub fn ironman(&self) -> bool {
+        self.ironman
+    }
+
+    pub fn multiplayer(&self) -> bool {
+        self.multiplayer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{file::PdsFile, test_utils, tokens::embedded_tokens};
+
+    /// The ironman and multiplayer flags of each fixture
+    const FLAGS: &[(&str, bool, bool)] = &[
+        ("kandy2.bin.eu4", true, false),
+        ("af_Munso_867_Ironman.ck3", true, false),
+        ("observer1.5.rome", false, false),
+        ("1.10-ironman.hoi4", true, false),
+    ];
+
+    #[test]
+    fn summarizes_fixtures() {
+        for &(name, ironman, multiplayer) in FLAGS {
+            let Some(data) = test_utils::save(name) else {
+                continue;
+            };
+
+            let game = test_utils::SAVES
+                .iter()
+                .find(|(x, _)| *x == name)
+                .map(|(_, game)| *game)
+                .unwrap();
+
+            if embedded_tokens(game).empty {
+                continue;
+            }
+
+            let file = PdsFile::from_slice(game, &data).unwrap();
+            let summary = file.summary().unwrap();
+            assert_eq!(summary.game(), game, "{}", name);
+
+            let fields = [
+                PdsSummaryField::Version,
+                PdsSummaryField::Date,
+                PdsSummaryField::Player,
+            ];
+            for field in fields {
+                let value = summary.field(field).unwrap_or_default();
+                assert!(!value.is_empty(), "{}: {:?}", name, field);
+            }
+
+            let save_name = summary.field(PdsSummaryField::SaveName);
+            if game == PdsGame::Hoi4 {
+                assert_eq!(save_name, None, "{}", name);
+            } else {
+                assert!(save_name.is_some_and(|x| !x.is_empty()), "{}", name);
+            }
+
+            assert_eq!(summary.ironman(), ironman, "{}", name);
+            assert_eq!(summary.multiplayer(), multiplayer, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    path::Path,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+/// Reads a save from `assets/saves`, where CI downloads them. Returns `None`
+/// so that the calling test can be skipped when the save is missing.
+pub fn save(name: &str) -> Option<Vec<u8>> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("assets")
+        .join("saves")
+        .join(name);
+
+    match std::fs::read(&path) {
+        Ok(data) => Some(data),
+        Err(_) => {
+            eprintln!("skipping, {} is missing", path.display());
+            None
+        }
+    }
+}
+
+/// The saves that CI downloads and the game each belongs to
+pub const SAVES: &[(&str, crate::file::PdsGame)] = &[
+    ("kandy2.bin.eu4", crate::file::PdsGame::Eu4),
+    ("af_Munso_867_Ironman.ck3", crate::file::PdsGame::Ck3),
+    ("observer1.5.rome", crate::file::PdsGame::Imperator),
+    ("1.10-ironman.hoi4", crate::file::PdsGame::Hoi4),
+];
+
+static TOKENS: Mutex<()> = Mutex::new(());
+
+/// Serializes the tests that load tokens, as loaded tokens apply to every
+/// melt in the process
+pub fn lock_tokens() -> MutexGuard<'static, ()> {
+    TOKENS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Tokens that a test loaded for a game, which are unloaded when dropped so
+/// that they don't outlive the test even when one of its assertions fails
+pub struct LoadedTokens {
+    game: crate::file::PdsGame,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Drop for LoadedTokens {
+    fn drop(&mut self) {
+        // Extending with no tokens restores the embedded tokens
+        let mode = crate::tokens::PdsTokensMode::Extend;
+        let _ = crate::tokens::load_tokens(self.game, b"", mode);
+    }
+}
+
+/// Extends a game's tokens for the duration of a test, which holds the tokens
+/// lock until the returned guard is dropped
+pub fn load_tokens(game: crate::file::PdsGame, data: &[u8]) -> LoadedTokens {
+    let lock = lock_tokens();
+    crate::tokens::load_tokens(game, data, crate::tokens::PdsTokensMode::Extend).unwrap();
+    LoadedTokens { game, _lock: lock }
+}
+
+/// Builds an HOI4 binary save with a `player="FRA"` field followed by two
+/// fields keyed by a token id that the tokens loaded for the test don't name
+pub fn hoi4_binary_save(player: u16, unknown: u16) -> Vec<u8> {
+    const EQUAL: [u8; 2] = [0x01, 0x00];
+    const QUOTED: [u8; 2] = [0x0f, 0x00];
+    const U32: [u8; 2] = [0x14, 0x00];
+
+    let mut data = b"HOI4bin".to_vec();
+    data.extend_from_slice(&player.to_le_bytes());
+    data.extend_from_slice(&EQUAL);
+    data.extend_from_slice(&QUOTED);
+    data.extend_from_slice(&3u16.to_le_bytes());
+    data.extend_from_slice(b"FRA");
+
+    for value in [1u32, 2] {
+        data.extend_from_slice(&unknown.to_le_bytes());
+        data.extend_from_slice(&EQUAL);
+        data.extend_from_slice(&U32);
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    data
+}
+
+/// The plaintext metadata that each game that shares the jomini envelope
+/// writes, trimmed to the fields that identify the game
+pub const PLAINTEXT_METADATA: &[(crate::file::PdsGame, &str)] = &[
+    (
+        crate::file::PdsGame::Ck3,
+        "meta_data={\n\tversion=\"1.12.4\"\n\tmeta_date=867.1.1\n\t\
+         meta_player_name=\"Munso\"\n\tmeta_title_name=\"Kingdom of Munso\"\n}\n",
+    ),
+    (
+        crate::file::PdsGame::Vic3,
+        "meta_data={\n\tsave_label=\"autosave\"\n\tversion=\"1.5.13\"\n\t\
+         game_date=1836.1.1.1\n}\n",
+    ),
+    (
+        crate::file::PdsGame::Eu5,
+        "metadata={\n\tversion=\"1.0.0\"\n\tdate=1337.4.1\n}\n",
+    ),
+    (
+        crate::file::PdsGame::Imperator,
+        "version=\"2.0.4\"\ndate=450.10.1\nmeta_player_name=\"Rome\"\n",
+    ),
+];
+
+/// Builds an uncompressed plaintext save in the jomini envelope, whose header
+/// records the length of the metadata that precedes the gamestate
+pub fn plaintext_save(metadata: &str) -> Vec<u8> {
+    let mut data = format!("SAV010000000000{:08x}\n", metadata.len()).into_bytes();
+    data.extend_from_slice(metadata.as_bytes());
+    data.extend_from_slice(b"speed=1\n");
+    data
+}
@@ -1,32 +1,501 @@
-pub fn eu4_tokens_resolver() -> eu4save::BasicTokenResolver {
-    let data = include_bytes!("../assets/tokens/eu4.txt");
-    eu4save::BasicTokenResolver::from_text_lines(&data[..]).expect("embedded tokens invalid format")
+use crate::{errors::LibError, file::PdsGame};
+use jomini::binary::TokenResolver;
+use libc::size_t;
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    sync::{Arc, OnceLock, PoisonError, RwLock},
+};
+
+/// A table of binary token names indexed by token id
+#[derive(Debug, Clone, Default)]
+pub struct TokenTable {
+    names: Vec<Option<Box<str>>>,
+
+    /// Token ids by name, built on the first lookup by name
+    ids: OnceLock<HashMap<Box<str>, u16>>,
+}
+
+impl TokenTable {
+    /// Parses token lines in the form of `<id> <name>`, where the id is
+    /// either decimal or hexadecimal prefixed with `0x`. Blank lines are
+    /// skipped, and an id may only be given once.
+    pub fn parse(data: &[u8]) -> Result<Self, LibError> {
+        Self::parse_lines(data, true)
+    }
+
+    /// Parses the token files embedded at build time, which are generated
+    /// from the games and so may repeat an id, where the last name wins, or
+    /// have names that contain whitespace
+    pub fn parse_embedded(data: &[u8]) -> Result<Self, LibError> {
+        Self::parse_lines(data, false)
+    }
+
+    fn parse_lines(data: &[u8], strict: bool) -> Result<Self, LibError> {
+        let mut table = TokenTable::default();
+        for (i, line) in data.split(|&x| x == b'\n').enumerate() {
+            let invalid = |msg: &str| LibError::InvalidTokens {
+                line: i + 1,
+                msg: String::from(msg),
+            };
+
+            let line = std::str::from_utf8(line).map_err(|_| invalid("not valid utf-8"))?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (id, name) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| invalid("expected a token id and name"))?;
+
+            let id = match id.strip_prefix("0x") {
+                Some(hex) => u16::from_str_radix(hex, 16),
+                None => id.parse::<u16>(),
+            }
+            .map_err(|_| invalid("token id is not a 16 bit integer"))?;
+
+            let name = name.trim_start();
+            if strict && name.chars().any(char::is_whitespace) {
+                return Err(invalid("token name contains whitespace"));
+            }
+
+            if strict && table.resolve(id).is_some() {
+                return Err(invalid("token id is given more than once"));
+            }
+
+            table.insert(id, name);
+        }
+
+        Ok(table)
+    }
+
+    fn insert(&mut self, id: u16, name: &str) {
+        let index = usize::from(id);
+        if self.names.len() <= index {
+            self.names.resize(index + 1, None);
+        }
+        self.names[index] = Some(Box::from(name));
+    }
+
+    /// Returns the id of the token with the given name. The lowest id is
+    /// returned when multiple tokens share a name.
+    pub fn id(&self, name: &str) -> Option<u16> {
+        let ids = self.ids.get_or_init(|| {
+            let mut ids = HashMap::new();
+            for (id, name) in self.names.iter().enumerate() {
+                if let Some(name) = name {
+                    ids.entry(name.clone()).or_insert(id as u16);
+                }
+            }
+            ids
+        });
+
+        ids.get(name).copied()
+    }
+
+    /// Adds the tokens of another table, overwriting the names of any shared
+    /// token ids
+    pub fn extend(&mut self, other: &TokenTable) {
+        self.ids = OnceLock::new();
+        if self.names.len() < other.names.len() {
+            self.names.resize(other.names.len(), None);
+        }
+
+        for (name, other) in self.names.iter_mut().zip(other.names.iter()) {
+            if other.is_some() {
+                name.clone_from(other);
+            }
+        }
+    }
 }
 
-pub fn ck3_tokens_resolver() -> ck3save::BasicTokenResolver {
-    let data = include_bytes!("../assets/tokens/ck3.txt");
-    ck3save::BasicTokenResolver::from_text_lines(&data[..]).expect("embedded tokens invalid format")
+impl TokenResolver for TokenTable {
+    fn resolve(&self, token: u16) -> Option<&str> {
+        self.names.get(usize::from(token))?.as_deref()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.names.iter().all(Option::is_none)
+    }
 }
 
-pub fn vic3_tokens_resolver() -> vic3save::BasicTokenResolver {
-    let data = include_bytes!("../assets/tokens/vic3.txt");
-    vic3save::BasicTokenResolver::from_text_lines(&data[..])
-        .expect("embedded tokens invalid format")
+/// A token table that is shared between melts
+#[derive(Debug, Clone)]
+pub struct SharedTokens(Arc<TokenTable>);
+
+impl SharedTokens {
+    pub fn id(&self, name: &str) -> Option<u16> {
+        self.0.id(name)
+    }
+
+    pub fn table(&self) -> &TokenTable {
+        &self.0
+    }
 }
 
-pub fn imperator_tokens_resolver() -> imperator_save::BasicTokenResolver {
-    let data = include_bytes!("../assets/tokens/imperator.txt");
-    imperator_save::BasicTokenResolver::from_text_lines(&data[..])
-        .expect("embedded tokens invalid format")
+impl TokenResolver for SharedTokens {
+    fn resolve(&self, token: u16) -> Option<&str> {
+        self.0.resolve(token)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 }
 
-pub fn hoi4_tokens_resolver() -> hoi4save::BasicTokenResolver {
-    let data = include_bytes!("../assets/tokens/hoi4.txt");
-    hoi4save::BasicTokenResolver::from_text_lines(&data[..])
-        .expect("embedded tokens invalid format")
+/// How loaded tokens are combined with the tokens a game already has
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdsTokensMode {
+    /// The tokens are added to the embedded tokens and any previously loaded
+    /// tokens, taking precedence over tokens with the same id
+    Extend = 0,
+
+    /// The tokens are used instead of the embedded tokens and any previously
+    /// loaded tokens. At least one token must be given, as empty data
+    /// restores the embedded tokens, which is only done when extending.
+    Replace = 1,
+}
+
+impl TryFrom<u32> for PdsTokensMode {
+    type Error = LibError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(PdsTokensMode::Extend),
+            1 => Ok(PdsTokensMode::Replace),
+            _ => Err(LibError::invalid_enum("PdsTokensMode", value)),
+        }
+    }
+}
+
+/// The embedded tokens of a game and the tokens that callers have loaded at
+/// runtime to supplement or replace them
+struct GameTokens {
+    embedded: &'static [u8],
+    state: RwLock<TokenState>,
+}
+
+struct TokenState {
+    loaded: Option<TokenTable>,
+
+    /// True when the loaded tokens are used instead of the embedded tokens
+    replaced: bool,
+
+    /// The embedded tokens merged with the loaded tokens, built on first use
+    /// and discarded when other tokens are loaded
+    resolver: Option<SharedTokens>,
+}
+
+impl GameTokens {
+    const fn new(embedded: &'static [u8]) -> Self {
+        GameTokens {
+            embedded,
+            state: RwLock::new(TokenState {
+                loaded: None,
+                replaced: false,
+                resolver: None,
+            }),
+        }
+    }
+
+    fn resolver(&self) -> Result<SharedTokens, LibError> {
+        let state = self.state.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some(resolver) = state.resolver.as_ref() {
+            return Ok(resolver.clone());
+        }
+        drop(state);
+
+        // Another thread may have built the resolver while the lock was
+        // released, so check again before building it
+        let mut state = self.state.write().unwrap_or_else(PoisonError::into_inner);
+        if let Some(resolver) = state.resolver.as_ref() {
+            return Ok(resolver.clone());
+        }
+
+        let mut table = if state.replaced {
+            TokenTable::default()
+        } else {
+            TokenTable::parse_embedded(self.embedded)?
+        };
+
+        if let Some(loaded) = state.loaded.as_ref() {
+            table.extend(loaded);
+        }
+
+        let resolver = SharedTokens(Arc::new(table));
+        state.resolver = Some(resolver.clone());
+        Ok(resolver)
+    }
+
+    fn load(&self, tokens: TokenTable, mode: PdsTokensMode) {
+        let mut state = self.state.write().unwrap_or_else(PoisonError::into_inner);
+        match (mode, state.loaded.as_mut()) {
+            _ if tokens.is_empty() => {
+                state.loaded = None;
+                state.replaced = false;
+            }
+            (PdsTokensMode::Extend, Some(loaded)) => loaded.extend(&tokens),
+            (PdsTokensMode::Extend, None) => state.loaded = Some(tokens),
+            (PdsTokensMode::Replace, _) => {
+                state.loaded = Some(tokens);
+                state.replaced = true;
+            }
+        }
+        state.resolver = None;
+    }
+}
+
+static EU4_TOKENS: GameTokens = GameTokens::new(include_bytes!("../assets/tokens/eu4.txt"));
+static CK3_TOKENS: GameTokens = GameTokens::new(include_bytes!("../assets/tokens/ck3.txt"));
+static VIC3_TOKENS: GameTokens = GameTokens::new(include_bytes!("../assets/tokens/vic3.txt"));
+static IMPERATOR_TOKENS: GameTokens =
+    GameTokens::new(include_bytes!("../assets/tokens/imperator.txt"));
+static HOI4_TOKENS: GameTokens = GameTokens::new(include_bytes!("../assets/tokens/hoi4.txt"));
+static EU5_TOKENS: GameTokens = GameTokens::new(include_bytes!("../assets/tokens/eu5.txt"));
+
+fn game_tokens(game: PdsGame) -> Option<&'static GameTokens> {
+    match game {
+        PdsGame::Eu4 => Some(&EU4_TOKENS),
+        PdsGame::Ck3 => Some(&CK3_TOKENS),
+        PdsGame::Imperator => Some(&IMPERATOR_TOKENS),
+        PdsGame::Hoi4 => Some(&HOI4_TOKENS),
+        PdsGame::Vic3 => Some(&VIC3_TOKENS),
+        PdsGame::Eu5 => Some(&EU5_TOKENS),
+        PdsGame::Unknown => None,
+    }
 }
 
-pub fn eu5_tokens_resolver() -> eu5save::BasicTokenResolver {
-    let data = include_bytes!("../assets/tokens/eu5.txt");
-    eu5save::BasicTokenResolver::from_text_lines(&data[..]).expect("embedded tokens invalid format")
+/// Describes the token file that was embedded in the library for a game
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PdsEmbeddedTokens {
+    /// The number of tokens in the file
+    pub count: size_t,
+
+    /// The 64 bit FNV-1a hash of the file's contents
+    pub hash: u64,
+
+    /// True when the library was built with an empty token file, as happens
+    /// when token files are missing at build time
+    pub empty: bool,
+}
+
+/// Returns a description of the token file embedded for a game
+pub fn embedded_tokens(game: PdsGame) -> PdsEmbeddedTokens {
+    let data = match game_tokens(game) {
+        Some(tokens) => tokens.embedded,
+        None => return PdsEmbeddedTokens::default(),
+    };
+
+    let count = data
+        .split(|&x| x == b'\n')
+        .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+        .count();
+
+    let hash = data.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, &x| {
+        (hash ^ u64::from(x)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+
+    PdsEmbeddedTokens {
+        count,
+        hash,
+        empty: count == 0,
+    }
+}
+
+/// Loads tokens for a game that are used for all subsequent melts, either in
+/// addition to or instead of the tokens the game already has. Extending with
+/// empty data restores the embedded tokens, while replacing with empty data
+/// is an error.
+pub fn load_tokens(game: PdsGame, data: &[u8], mode: PdsTokensMode) -> Result<(), LibError> {
+    let tokens = game_tokens(game).ok_or(LibError::UnknownGame)?;
+    let table = TokenTable::parse(data)?;
+    if mode == PdsTokensMode::Replace && table.is_empty() {
+        return Err(LibError::InvalidArgument(String::from(
+            "replacing tokens requires at least one token",
+        )));
+    }
+    tokens.load(table, mode);
+    Ok(())
+}
+
+/// Returns the resolver of a game's embedded and loaded tokens
+pub fn tokens_resolver(game: PdsGame) -> Result<SharedTokens, LibError> {
+    game_tokens(game).ok_or(LibError::UnknownGame)?.resolver()
+}
+
+pub fn eu4_tokens_resolver() -> Result<SharedTokens, LibError> {
+    EU4_TOKENS.resolver()
+}
+
+pub fn ck3_tokens_resolver() -> Result<SharedTokens, LibError> {
+    CK3_TOKENS.resolver()
+}
+
+pub fn vic3_tokens_resolver() -> Result<SharedTokens, LibError> {
+    VIC3_TOKENS.resolver()
+}
+
+pub fn imperator_tokens_resolver() -> Result<SharedTokens, LibError> {
+    IMPERATOR_TOKENS.resolver()
+}
+
+pub fn hoi4_tokens_resolver() -> Result<SharedTokens, LibError> {
+    HOI4_TOKENS.resolver()
+}
+
+pub fn eu5_tokens_resolver() -> Result<SharedTokens, LibError> {
+    EU5_TOKENS.resolver()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    fn parse_error(data: &[u8]) -> (usize, String) {
+        match TokenTable::parse(data) {
+            Err(LibError::InvalidTokens { line, msg }) => (line, msg),
+            x => panic!("expected invalid tokens, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn parses_decimal_and_hexadecimal_ids() {
+        let table = TokenTable::parse(b"0x2d82 date\r\n\n  11 player  \n").unwrap();
+        assert_eq!(table.resolve(0x2d82), Some("date"));
+        assert_eq!(table.resolve(11), Some("player"));
+        assert_eq!(table.resolve(12), None);
+        assert_eq!(table.id("player"), Some(11));
+    }
+
+    #[test]
+    fn empty_input_has_no_tokens() {
+        for data in [&b""[..], b"\n", b"  \r\n\t\n"] {
+            let table = TokenTable::parse(data).unwrap();
+            assert!(table.is_empty());
+            assert_eq!(table.resolve(0), None);
+        }
+    }
+
+    #[test]
+    fn malformed_lines_report_the_line() {
+        assert_eq!(
+            parse_error(b"1 a\nplayer"),
+            (2, String::from("expected a token id and name"))
+        );
+        assert_eq!(
+            parse_error(b"0x10000 date"),
+            (1, String::from("token id is not a 16 bit integer"))
+        );
+        assert_eq!(
+            parse_error(b"\n\nabc date"),
+            (3, String::from("token id is not a 16 bit integer"))
+        );
+        assert_eq!(
+            parse_error(b"1 two words"),
+            (1, String::from("token name contains whitespace"))
+        );
+        assert_eq!(parse_error(b"1 \xff"), (1, String::from("not valid utf-8")));
+    }
+
+    #[test]
+    fn duplicate_ids_are_an_error() {
+        assert_eq!(
+            parse_error(b"0x10 date\n16 player"),
+            (2, String::from("token id is given more than once"))
+        );
+    }
+
+    #[test]
+    fn embedded_tokens_allow_repeated_ids_and_spaced_names() {
+        let table = TokenTable::parse_embedded(b"1 first\n0x1 last\n2 two words").unwrap();
+        assert_eq!(table.resolve(1), Some("last"));
+        assert_eq!(table.resolve(2), Some("two words"));
+    }
+
+    #[test]
+    fn every_embedded_token_file_parses() {
+        let games = [
+            PdsGame::Eu4,
+            PdsGame::Ck3,
+            PdsGame::Imperator,
+            PdsGame::Hoi4,
+            PdsGame::Vic3,
+            PdsGame::Eu5,
+        ];
+
+        for game in games {
+            let tokens = game_tokens(game).unwrap();
+            let table = TokenTable::parse_embedded(tokens.embedded);
+            assert!(table.is_ok(), "{}: {:?}", game, table.err());
+        }
+    }
+
+    #[test]
+    fn replacing_with_no_tokens_is_an_error() {
+        let _tokens = test_utils::lock_tokens();
+        let err = load_tokens(PdsGame::Eu4, b"\n", PdsTokensMode::Replace);
+        assert!(matches!(err, Err(LibError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn loaded_tokens_extend_or_replace() {
+        let _tokens = test_utils::lock_tokens();
+        let embedded = TokenTable::parse_embedded(EU4_TOKENS.embedded).unwrap();
+        let embedded_id = (0..=u16::MAX).find(|&x| embedded.resolve(x).is_some());
+
+        load_tokens(PdsGame::Eu4, b"0xfff0 first", PdsTokensMode::Extend).unwrap();
+        load_tokens(PdsGame::Eu4, b"0xfff1 second", PdsTokensMode::Extend).unwrap();
+        let extended = eu4_tokens_resolver().unwrap();
+
+        load_tokens(PdsGame::Eu4, b"0xfff2 third", PdsTokensMode::Replace).unwrap();
+        let replaced = eu4_tokens_resolver().unwrap();
+
+        load_tokens(PdsGame::Eu4, b"", PdsTokensMode::Extend).unwrap();
+        let restored = eu4_tokens_resolver().unwrap();
+
+        assert_eq!(extended.resolve(0xfff0), Some("first"));
+        assert_eq!(extended.resolve(0xfff1), Some("second"));
+        assert_eq!(replaced.resolve(0xfff0), None);
+        assert_eq!(replaced.resolve(0xfff2), Some("third"));
+        assert_eq!(restored.resolve(0xfff2), None);
+        if let Some(id) = embedded_id {
+            assert!(extended.resolve(id).is_some());
+            assert!(replaced.resolve(id).is_none());
+            assert!(restored.resolve(id).is_some());
+        }
+    }
+
+    #[test]
+    fn concurrent_first_use_builds_one_resolver() {
+        let _tokens = test_utils::lock_tokens();
+        for _ in 0..16 {
+            load_tokens(PdsGame::Hoi4, b"", PdsTokensMode::Extend).unwrap();
+            let barrier = std::sync::Barrier::new(8);
+            let resolvers: Vec<_> = std::thread::scope(|scope| {
+                let threads: Vec<_> = (0..8)
+                    .map(|_| {
+                        scope.spawn(|| {
+                            barrier.wait();
+                            hoi4_tokens_resolver().unwrap()
+                        })
+                    })
+                    .collect();
+                threads.into_iter().map(|x| x.join().unwrap()).collect()
+            });
+
+            for resolver in &resolvers[1..] {
+                assert!(Arc::ptr_eq(&resolvers[0].0, &resolver.0));
+            }
+        }
+    }
+
+    #[test]
+    fn token_modes_from_c_are_validated() {
+        assert_eq!(PdsTokensMode::try_from(1).unwrap(), PdsTokensMode::Replace);
+        assert!(PdsTokensMode::try_from(2).is_err());
+    }
 }
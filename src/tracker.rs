@@ -0,0 +1,480 @@
+//! Follows melted output as it is written so that what a melt wrote can be
+//! reported without parsing the output afterwards, and counts the tokens that
+//! the melter couldn't resolve.
+
+use crate::{
+    diagnostics::{scalar_kind, unknown_id, PdsValueKind, TokenContext},
+    errors::LibError,
+    lexer::{Lexeme, LexemeSink, Lexer},
+    melter::UnknownTokens,
+    options::MeltOptions,
+};
+use jomini::binary::TokenResolver;
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// The number of times each unknown token was looked up by the melter
+type Occurrences = Arc<Mutex<HashMap<u16, u64>>>;
+
+/// A writer that forwards melted output to another writer. When diagnostics
+/// are enabled, it follows the unknown tokens, which the melter writes as
+/// `__unknown_0x<id>` scalars, and the keys of the fields being written.
+pub struct MeltTracker<W> {
+    inner: W,
+    follow: bool,
+    lexer: Lexer,
+    state: TrackState,
+    occurrences: Occurrences,
+}
+
+/// A token resolver that counts the lookups of tokens that it can't resolve
+pub struct CountingResolver<R> {
+    tokens: R,
+    occurrences: Occurrences,
+}
+
+impl<R: TokenResolver> TokenResolver for CountingResolver<R> {
+    fn resolve(&self, token: u16) -> Option<&str> {
+        let name = self.tokens.resolve(token);
+        if name.is_none() {
+            let mut occurrences = self
+                .occurrences
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            *occurrences.entry(token).or_default() += 1;
+        }
+        name
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+}
+
+#[derive(Debug, Default)]
+struct TrackState {
+    contexts: HashMap<u16, TokenContext>,
+
+    /// If a token was seen, as the first token may be the save's magic
+    started: bool,
+
+    /// The keys of the open containers separated by `/`
+    path: Vec<u8>,
+    frames: Vec<Frame>,
+
+    /// Scalars whose meaning depends on the token that follows them
+    pending: Pending,
+    key: Vec<u8>,
+    value: Vec<u8>,
+    quoted: bool,
+}
+
+#[derive(Debug)]
+struct Frame {
+    /// The length of the path before the container was opened
+    path_len: usize,
+
+    /// The unknown token that keys this container, until the first token in
+    /// the container tells if it's an object or an array
+    unknown: Option<u16>,
+
+    /// If the container is an object, once the first token in it tells
+    object: Option<bool>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+enum Pending {
+    #[default]
+    Nothing,
+
+    /// A scalar in `key` that is either a key or a value in an array
+    Scalar,
+
+    /// A key in `key` that was followed by an operator
+    Key,
+
+    /// A key in `key` and its value in `value`, which may tag a container
+    /// like `rgb { 1 2 3 }`
+    Value,
+}
+
+impl LexemeSink for TrackState {
+    fn lexeme(&mut self, lexeme: Lexeme) {
+        if !std::mem::replace(&mut self.started, true) {
+            if let Lexeme::Unquoted(x) = lexeme {
+                if x == b"EU4txt" || x == b"HOI4txt" || x.starts_with(b"SAV") {
+                    return;
+                }
+            }
+        }
+
+        self.track(lexeme);
+    }
+}
+
+impl TrackState {
+    fn track(&mut self, lexeme: Lexeme) {
+        match (std::mem::take(&mut self.pending), lexeme) {
+            (Pending::Nothing, Lexeme::Open) => {
+                self.decide(PdsValueKind::Array);
+                self.open(false, None);
+            }
+            (Pending::Nothing, Lexeme::Close) => self.close(),
+            (Pending::Nothing, Lexeme::Operator(_)) => {}
+            (Pending::Nothing, Lexeme::Unquoted(x)) => self.scalar(x, false),
+            (Pending::Nothing, Lexeme::Quoted(x)) => self.scalar(x, true),
+            (Pending::Scalar, Lexeme::Operator(_)) => {
+                self.decide(PdsValueKind::Object);
+                self.pending = Pending::Key;
+            }
+            (Pending::Scalar, lexeme) => {
+                self.decide(PdsValueKind::Array);
+                if let Some(id) = unknown_id(&self.key).filter(|_| !self.quoted) {
+                    let path = String::from_utf8_lossy(&self.path).into_owned();
+                    self.record(id, PdsValueKind::Token, path);
+                }
+                self.track(lexeme);
+            }
+            (Pending::Key, Lexeme::Unquoted(x)) => self.value(x, false),
+            (Pending::Key, Lexeme::Quoted(x)) => self.value(x, true),
+            (Pending::Key, Lexeme::Open) => {
+                let unknown = unknown_id(&self.key);
+                self.open(true, unknown);
+            }
+            (Pending::Key, lexeme) => self.track(lexeme),
+            (Pending::Value, Lexeme::Open) => {
+                self.field(PdsValueKind::Array);
+                self.open(true, None);
+            }
+            (Pending::Value, lexeme) => {
+                let kind = if self.quoted {
+                    PdsValueKind::Text
+                } else {
+                    scalar_kind(&self.value)
+                };
+                self.field(kind);
+                self.track(lexeme);
+            }
+        }
+    }
+
+    /// Resolves what is still pending at the end of the output
+    fn finish(&mut self) {
+        if !matches!(self.pending, Pending::Nothing) {
+            self.track(Lexeme::Close);
+        }
+    }
+
+    fn scalar(&mut self, data: &[u8], quoted: bool) {
+        self.key.clear();
+        self.key.extend_from_slice(data);
+        self.quoted = quoted;
+        self.pending = Pending::Scalar;
+    }
+
+    fn value(&mut self, data: &[u8], quoted: bool) {
+        self.value.clear();
+        self.value.extend_from_slice(data);
+        self.quoted = quoted;
+        self.pending = Pending::Value;
+    }
+
+    /// Records the unknown tokens of a field with a scalar or tagged value
+    fn field(&mut self, kind: PdsValueKind) {
+        let key = unknown_id(&self.key);
+        let value = unknown_id(&self.value).filter(|_| !self.quoted);
+        if key.is_none() && value.is_none() {
+            return;
+        }
+
+        let path = self.key_path_with(&self.key);
+        if let Some(id) = key {
+            self.record(id, kind, path.clone());
+        }
+
+        if let Some(id) = value {
+            self.record(id, PdsValueKind::Token, path);
+        }
+    }
+
+    /// Opens a container, which is the value of the key in `key` when keyed
+    fn open(&mut self, keyed: bool, unknown: Option<u16>) {
+        let path_len = self.path.len();
+        if keyed {
+            if !self.path.is_empty() {
+                self.path.push(b'/');
+            }
+            self.path.extend_from_slice(&self.key);
+        }
+
+        self.frames.push(Frame {
+            path_len,
+            unknown,
+            object: None,
+        });
+    }
+
+    fn close(&mut self) {
+        // Empty containers are arrays
+        self.decide(PdsValueKind::Array);
+        if let Some(frame) = self.frames.pop() {
+            self.path.truncate(frame.path_len);
+        }
+    }
+
+    /// Records the kind of the innermost container once it is known, and
+    /// for the unknown token that keys the container
+    fn decide(&mut self, kind: PdsValueKind) {
+        let Some(frame) = self.frames.last_mut() else {
+            return;
+        };
+
+        frame.object.get_or_insert(kind == PdsValueKind::Object);
+        if let Some(id) = frame.unknown.take() {
+            let path = String::from_utf8_lossy(&self.path).into_owned();
+            self.record(id, kind, path);
+        }
+    }
+
+    fn record(&mut self, id: u16, kind: PdsValueKind, path: String) {
+        let context = self
+            .contexts
+            .entry(id)
+            .or_insert_with(|| TokenContext::new(path));
+        context.add_kind(kind);
+    }
+
+    /// The keys of the open containers and of the field being written
+    fn key_path(&self) -> Option<String> {
+        // The operator is written with the value, so a scalar that may start
+        // a field is the key of the field whose value failed to melt
+        let key = match self.pending {
+            Pending::Key => Some(&self.key),
+            Pending::Scalar if self.frames.last().is_none_or(|x| x.object != Some(false)) => {
+                Some(&self.key)
+            }
+            _ => None,
+        };
+
+        let path = match key {
+            Some(key) => self.key_path_with(key),
+            None => String::from_utf8_lossy(&self.path).into_owned(),
+        };
+
+        if path.is_empty() {
+            None
+        } else {
+            Some(path)
+        }
+    }
+
+    fn key_path_with(&self, key: &[u8]) -> String {
+        let mut path = self.path.clone();
+        if !path.is_empty() {
+            path.push(b'/');
+        }
+        path.extend_from_slice(key);
+        String::from_utf8_lossy(&path).into_owned()
+    }
+}
+
+/// The key path that was being written at the end of the melted output
+pub fn key_path(output: &[u8]) -> Option<String> {
+    let mut lexer = Lexer::default();
+    let mut state = TrackState::default();
+    lexer.feed(output, &mut state);
+    lexer.finish(&mut state);
+    state.key_path()
+}
+
+impl<W: Write> MeltTracker<W> {
+    /// The output is only followed when diagnostics are enabled, as lexing
+    /// it has a cost
+    pub fn new(inner: W, options: &MeltOptions) -> Self {
+        MeltTracker {
+            inner,
+            follow: options.diagnostics(),
+            lexer: Lexer::default(),
+            state: TrackState::default(),
+            occurrences: Occurrences::default(),
+        }
+    }
+
+    /// Wraps the tokens given to the melter so that the unknown tokens are
+    /// counted as the melter looks them up
+    pub fn resolver<R: TokenResolver>(&self, tokens: R) -> CountingResolver<R> {
+        CountingResolver {
+            tokens,
+            occurrences: Arc::clone(&self.occurrences),
+        }
+    }
+
+    /// Attaches the key path that was being written to a failed melt when
+    /// the output is followed. The melter writes whole tokens, so a token at
+    /// the end of the output is complete even without a delimiter after it.
+    pub fn with_key_path<T, E>(&mut self, result: Result<T, E>) -> Result<T, LibError>
+    where
+        E: Into<LibError>,
+    {
+        result.map_err(|e| {
+            if !self.follow {
+                return e.into();
+            }
+
+            self.lexer.finish(&mut self.state);
+            e.into().with_key_path(self.state.key_path())
+        })
+    }
+
+    /// Describes the unknown tokens that the melter reported, with the
+    /// number of times the melter looked up each and, when the output is
+    /// followed, where each was first written
+    pub fn unknown_tokens<'a, I>(mut self, ids: I) -> UnknownTokens
+    where
+        I: IntoIterator<Item = &'a u16>,
+    {
+        self.lexer.finish(&mut self.state);
+        self.state.finish();
+        let occurrences = self
+            .occurrences
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let counts = ids
+            .into_iter()
+            .map(|id| (*id, occurrences.get(id).copied().unwrap_or_default()))
+            .collect();
+        UnknownTokens::new(counts, self.state.contexts)
+    }
+}
+
+impl<W: Write> Write for MeltTracker<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if self.follow {
+            self.lexer.feed(&buf[..written], &mut self.state);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens::TokenTable;
+
+    fn diagnostics() -> MeltOptions {
+        let mut options = MeltOptions::default();
+        options.set_diagnostics(true);
+        options
+    }
+
+    #[test]
+    fn key_path_is_the_field_being_written() {
+        assert_eq!(key_path(b"EU4txt"), None);
+        assert_eq!(key_path(b"SAV0103f4e2000004a4000006\n"), None);
+        assert_eq!(
+            key_path(b"date=1444.11.11 player"),
+            Some(String::from("player"))
+        );
+        assert_eq!(key_path(b"a={ b={ c=1 d"), Some(String::from("a/b/d")));
+        assert_eq!(key_path(b"a={ b={ c=1 d="), Some(String::from("a/b/d")));
+        assert_eq!(key_path(b"a={ b={ c=1 "), Some(String::from("a/b")));
+        assert_eq!(key_path(b"a={ b={ c=1 } "), Some(String::from("a")));
+        assert_eq!(key_path(b"a={ 1 2 "), Some(String::from("a")));
+        assert_eq!(key_path(b"a={ { 1 } 2 "), Some(String::from("a")));
+        assert_eq!(
+            key_path(b"a={ name=\"x y\" \"quoted key\""),
+            Some(String::from("a/quoted key"))
+        );
+    }
+
+    #[test]
+    fn records_where_unknown_tokens_are_written() {
+        let data = b"EU4txt\nprovinces={ -1={ __unknown_0x1=yes __unknown_0x2={ a=1 } } }\n\
+            list={ 1 __unknown_0x3 } color=__unknown_0x4 { 1 2 3 } \
+            __unknown_0x5={ } __unknown_0x6={ 1 2 } __unknown_0x1=1444.11.11";
+
+        let mut output = Vec::new();
+        let mut tracker = MeltTracker::new(&mut output, &diagnostics());
+        for chunk in data.chunks(7) {
+            tracker.write_all(chunk).unwrap();
+        }
+
+        let unknown = tracker.unknown_tokens(&[1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(output, data.to_vec());
+
+        let context = |id: u16| {
+            let index = unknown.tokens().iter().position(|x| x.id == id).unwrap();
+            let context = unknown.context(index).unwrap();
+            (context.path().to_string(), context.kinds())
+        };
+
+        let kinds = |kinds: &[PdsValueKind]| kinds.iter().fold(0, |acc, &x| acc | x as u32);
+        assert_eq!(
+            context(1),
+            (
+                String::from("provinces/-1/__unknown_0x1"),
+                kinds(&[PdsValueKind::Bool, PdsValueKind::Date])
+            )
+        );
+        assert_eq!(
+            context(2),
+            (
+                String::from("provinces/-1/__unknown_0x2"),
+                kinds(&[PdsValueKind::Object])
+            )
+        );
+        assert_eq!(
+            context(3),
+            (String::from("list"), kinds(&[PdsValueKind::Token]))
+        );
+        assert_eq!(
+            context(4),
+            (String::from("color"), kinds(&[PdsValueKind::Token]))
+        );
+        assert_eq!(
+            context(5),
+            (String::from("__unknown_0x5"), kinds(&[PdsValueKind::Array]))
+        );
+        assert_eq!(
+            context(6),
+            (String::from("__unknown_0x6"), kinds(&[PdsValueKind::Array]))
+        );
+        assert_eq!(context(7), (String::new(), 0));
+    }
+
+    #[test]
+    fn key_path_is_only_followed_with_diagnostics() {
+        let data = b"a={ b=1 c";
+        let mut tracker = MeltTracker::new(Vec::new(), &MeltOptions::default());
+        tracker.write_all(data).unwrap();
+        let err = tracker.with_key_path(Err::<(), _>(LibError::UnknownGame));
+        assert_eq!(err.unwrap_err().key_path(), None);
+
+        let mut tracker = MeltTracker::new(Vec::new(), &diagnostics());
+        tracker.write_all(data).unwrap();
+        let err = tracker.with_key_path(Err::<(), _>(LibError::UnknownGame));
+        assert_eq!(err.unwrap_err().key_path(), Some("a/c"));
+    }
+
+    #[test]
+    fn counts_the_tokens_that_are_not_resolved() {
+        let tokens = TokenTable::parse(b"1 known").unwrap();
+        let tracker = MeltTracker::new(Vec::new(), &MeltOptions::default());
+        let resolver = tracker.resolver(tokens);
+        for id in [1, 2, 3, 2] {
+            resolver.resolve(id);
+        }
+
+        let unknown = tracker.unknown_tokens(&[2, 3]);
+        let occurrences: Vec<_> = unknown.tokens().iter().map(|x| x.occurrences).collect();
+        assert_eq!(occurrences, vec![2, 1]);
+    }
+}
@@ -0,0 +1,87 @@
+//! Converts panics into errors that describe the panic. While librakaly is
+//! running on a thread, a panic hook records the message and location of a
+//! panic on that thread so that nothing is written to the host's stderr.
+//! Every other panic is passed to the hook that was installed before.
+
+use crate::errors::LibError;
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    panic::UnwindSafe,
+    sync::Once,
+};
+
+thread_local! {
+    static PANIC_MESSAGE: RefCell<Option<String>> = const { RefCell::new(None) };
+
+    /// The number of `catch_unwind` calls that the thread is inside of
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+static HOOK: Once = Once::new();
+
+/// Invokes a closure, returning an error with the panic's message and
+/// location if the closure panics
+pub fn catch_unwind<F, R>(f: F) -> Result<R, LibError>
+where
+    F: FnOnce() -> R + UnwindSafe,
+{
+    HOOK.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if DEPTH.try_with(|x| x.get()).unwrap_or(0) == 0 {
+                previous(info);
+                return;
+            }
+
+            let msg = payload_message(info.payload());
+            let msg = match info.location() {
+                Some(location) => format!("{} at {}", msg, location),
+                None => msg,
+            };
+            let _ = PANIC_MESSAGE.try_with(|x| *x.borrow_mut() = Some(msg));
+        }));
+    });
+
+    PANIC_MESSAGE.with(|x| x.borrow_mut().take());
+    DEPTH.with(|x| x.set(x.get() + 1));
+    let result = std::panic::catch_unwind(f);
+    DEPTH.with(|x| x.set(x.get() - 1));
+
+    result.map_err(|payload| {
+        let msg = PANIC_MESSAGE
+            .with(|x| x.borrow_mut().take())
+            .unwrap_or_else(|| payload_message(&*payload));
+        LibError::Panic(msg)
+    })
+}
+
+fn payload_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        String::from(*msg)
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        String::from("panic with a non-string payload")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_panics_inside_catch_unwind_are_recorded() {
+        let err = catch_unwind(|| panic!("inside")).unwrap_err();
+        assert!(err.to_string().contains("inside"), "{}", err);
+        assert!(err.to_string().contains("unwind.rs"), "{}", err);
+
+        let nested = catch_unwind(|| catch_unwind(|| panic!("nested")).unwrap_err());
+        assert!(nested.unwrap().to_string().contains("nested"));
+
+        // Panics outside of librakaly go to the previous hook instead
+        assert!(std::panic::catch_unwind(|| panic!("outside")).is_err());
+        assert!(PANIC_MESSAGE.with(|x| x.borrow().is_none()));
+        assert_eq!(DEPTH.with(|x| x.get()), 0);
+    }
+}